@@ -12,6 +12,9 @@ pub struct Model {
     pub images: Option<Vec<String>>,
     pub created_at: DateTimeWithTimeZone,
     pub user_id: Uuid,
+    pub version: i32,
+    pub updated_at: DateTimeWithTimeZone,
+    pub tags: Vec<String>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
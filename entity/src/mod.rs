@@ -2,6 +2,14 @@
 
 pub mod prelude;
 
+pub mod api_token;
+pub mod auth_token;
 pub mod blog;
+pub mod password_credential;
+pub mod recovery_code;
+pub mod refresh_token;
+pub mod sea_orm_active_enums;
 pub mod session;
+pub mod totp_credential;
 pub mod user;
+pub mod user_identity;
@@ -1,5 +1,12 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.12.15
 
+pub use super::api_token::Entity as ApiToken;
+pub use super::auth_token::Entity as AuthToken;
 pub use super::blog::Entity as Blog;
+pub use super::password_credential::Entity as PasswordCredential;
+pub use super::recovery_code::Entity as RecoveryCode;
+pub use super::refresh_token::Entity as RefreshToken;
 pub use super::session::Entity as Session;
+pub use super::totp_credential::Entity as TotpCredential;
 pub use super::user::Entity as User;
+pub use super::user_identity::Entity as UserIdentity;
@@ -12,6 +12,12 @@ pub struct Model {
     #[sea_orm(unique)]
     pub csfr_token: String,
     pub user_id: Uuid,
+    pub mfa_pending: bool,
+    pub created_at: DateTimeWithTimeZone,
+    pub remember_me: bool,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+    pub last_seen_at: DateTimeWithTimeZone,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
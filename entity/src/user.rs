@@ -1,5 +1,6 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.12.15
 
+use super::sea_orm_active_enums::Role;
 use sea_orm::entity::prelude::*;
 
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
@@ -10,14 +11,45 @@ pub struct Model {
     pub email: String,
     #[sea_orm(primary_key, auto_increment = false)]
     pub uuid: Uuid,
+    pub role: Role,
+    pub email_verified_at: Option<DateTimeWithTimeZone>,
+    pub version: i32,
+    pub updated_at: DateTimeWithTimeZone,
+    pub created_at: DateTimeWithTimeZone,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
+    #[sea_orm(has_many = "super::api_token::Entity")]
+    ApiToken,
+    #[sea_orm(has_many = "super::auth_token::Entity")]
+    AuthToken,
     #[sea_orm(has_many = "super::blog::Entity")]
     Blog,
+    #[sea_orm(has_one = "super::password_credential::Entity")]
+    PasswordCredential,
+    #[sea_orm(has_many = "super::recovery_code::Entity")]
+    RecoveryCode,
+    #[sea_orm(has_many = "super::refresh_token::Entity")]
+    RefreshToken,
     #[sea_orm(has_many = "super::session::Entity")]
     Session,
+    #[sea_orm(has_one = "super::totp_credential::Entity")]
+    TotpCredential,
+    #[sea_orm(has_many = "super::user_identity::Entity")]
+    UserIdentity,
+}
+
+impl Related<super::api_token::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ApiToken.def()
+    }
+}
+
+impl Related<super::auth_token::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::AuthToken.def()
+    }
 }
 
 impl Related<super::blog::Entity> for Entity {
@@ -26,10 +58,40 @@ impl Related<super::blog::Entity> for Entity {
     }
 }
 
+impl Related<super::password_credential::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::PasswordCredential.def()
+    }
+}
+
+impl Related<super::recovery_code::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::RecoveryCode.def()
+    }
+}
+
+impl Related<super::refresh_token::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::RefreshToken.def()
+    }
+}
+
 impl Related<super::session::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::Session.def()
     }
 }
 
+impl Related<super::totp_credential::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::TotpCredential.def()
+    }
+}
+
+impl Related<super::user_identity::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::UserIdentity.def()
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
@@ -3,6 +3,18 @@ pub use sea_orm_migration::prelude::*;
 mod m20220101_000001_create_table;
 mod m20240730_022807_create_table_blog;
 mod m20240730_022807_create_table_sessions;
+mod m20261018_000001_add_role_to_user;
+mod m20261018_000002_add_oidc_subject_to_user;
+mod m20261018_000003_create_table_user_identity;
+mod m20261018_000004_create_table_password_credential;
+mod m20261018_000005_create_table_auth_token;
+mod m20261018_000006_create_table_totp_credential;
+mod m20261018_000007_create_table_api_token;
+mod m20261018_000008_create_table_refresh_token;
+mod m20261018_000009_add_lifetime_to_session;
+mod m20261018_000010_add_device_to_session;
+mod m20261018_000011_add_version_to_blog_and_user;
+mod m20261018_000012_add_listing_indexes;
 
 
 pub struct Migrator;
@@ -14,6 +26,18 @@ impl MigratorTrait for Migrator {
             Box::new(m20220101_000001_create_table::Migration),
             Box::new(m20240730_022807_create_table_blog::Migration),
             Box::new(m20240730_022807_create_table_sessions::Migration),
+            Box::new(m20261018_000001_add_role_to_user::Migration),
+            Box::new(m20261018_000002_add_oidc_subject_to_user::Migration),
+            Box::new(m20261018_000003_create_table_user_identity::Migration),
+            Box::new(m20261018_000004_create_table_password_credential::Migration),
+            Box::new(m20261018_000005_create_table_auth_token::Migration),
+            Box::new(m20261018_000006_create_table_totp_credential::Migration),
+            Box::new(m20261018_000007_create_table_api_token::Migration),
+            Box::new(m20261018_000008_create_table_refresh_token::Migration),
+            Box::new(m20261018_000009_add_lifetime_to_session::Migration),
+            Box::new(m20261018_000010_add_device_to_session::Migration),
+            Box::new(m20261018_000011_add_version_to_blog_and_user::Migration),
+            Box::new(m20261018_000012_add_listing_indexes::Migration),
         ]
     }
 }
@@ -0,0 +1,67 @@
+use sea_orm_migration::prelude::*;
+use sea_orm_migration::sea_query::extension::postgres::Type;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+
+        manager
+            .create_type(
+                Type::create()
+                    .as_enum(Role::Enum)
+                    .values([Role::Reader, Role::Author, Role::Editor, Role::Admin])
+                    .to_owned(),
+            )
+            .await?;
+
+        //existing users were all allowed to publish, so they start out as authors
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(User::Table)
+                    .add_column(
+                        ColumnDef::new(User::Role)
+                            .custom(Role::Enum)
+                            .not_null()
+                            .default(Expr::val("author").as_enum(Role::Enum)),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(User::Table)
+                    .drop_column(User::Role)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_type(Type::drop().name(Role::Enum).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    Role,
+}
+
+#[derive(DeriveIden)]
+enum Role {
+    #[sea_orm(iden = "role")]
+    Enum,
+    Reader,
+    Author,
+    Editor,
+    Admin,
+}
@@ -0,0 +1,111 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(UserIdentity::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(UserIdentity::Id).uuid().not_null().primary_key())
+                    .col(ColumnDef::new(UserIdentity::UserId).uuid().not_null())
+                    .col(ColumnDef::new(UserIdentity::Provider).string().not_null())
+                    .col(ColumnDef::new(UserIdentity::Subject).string().not_null())
+                    .col(ColumnDef::new(UserIdentity::Email).string().not_null())
+                    .col(ColumnDef::new(UserIdentity::CreatedAt)
+                        .timestamp_with_time_zone()
+                        .not_null()
+                        .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-user_identity-user_id")
+                            .from(UserIdentity::Table, UserIdentity::UserId)
+                            .to(User::Table, User::Uuid)
+                            .on_delete(ForeignKeyAction::Cascade)
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        //a subject is only unique within its provider
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-user_identity-provider-subject")
+                    .table(UserIdentity::Table)
+                    .col(UserIdentity::Provider)
+                    .col(UserIdentity::Subject)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        //every subject stored so far came from google
+        manager
+            .get_connection()
+            .execute_unprepared(
+                r#"INSERT INTO "user_identity" ("id", "user_id", "provider", "subject", "email")
+                   SELECT gen_random_uuid(), "uuid", 'google', "oidc_subject", "email"
+                   FROM "user" WHERE "oidc_subject" IS NOT NULL"#,
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(User::Table)
+                    .drop_column(User::OidcSubject)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(User::Table)
+                    .add_column(ColumnDef::new(User::OidcSubject).string().unique_key())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .get_connection()
+            .execute_unprepared(
+                r#"UPDATE "user" SET "oidc_subject" = "user_identity"."subject"
+                   FROM "user_identity"
+                   WHERE "user_identity"."user_id" = "user"."uuid" AND "user_identity"."provider" = 'google'"#,
+            )
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(UserIdentity::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum UserIdentity {
+    Table,
+    Id,
+    UserId,
+    Provider,
+    Subject,
+    Email,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    Uuid,
+    OidcSubject,
+}
@@ -0,0 +1,62 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(PasswordCredential::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(PasswordCredential::UserId).uuid().not_null().primary_key())
+                    .col(ColumnDef::new(PasswordCredential::PasswordHash).string().not_null())
+                    .col(ColumnDef::new(PasswordCredential::FailedAttempts)
+                        .integer()
+                        .not_null()
+                        .default(0),
+                    )
+                    .col(ColumnDef::new(PasswordCredential::LockedUntil).timestamp_with_time_zone())
+                    .col(ColumnDef::new(PasswordCredential::UpdatedAt)
+                        .timestamp_with_time_zone()
+                        .not_null()
+                        .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-password_credential-user_id")
+                            .from(PasswordCredential::Table, PasswordCredential::UserId)
+                            .to(User::Table, User::Uuid)
+                            .on_delete(ForeignKeyAction::Cascade)
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+
+        manager
+            .drop_table(Table::drop().table(PasswordCredential::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum PasswordCredential {
+    Table,
+    UserId,
+    PasswordHash,
+    FailedAttempts,
+    LockedUntil,
+    UpdatedAt,
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    Uuid,
+}
@@ -0,0 +1,121 @@
+use sea_orm_migration::prelude::*;
+use sea_orm_migration::sea_query::extension::postgres::Type;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+
+        manager
+            .create_type(
+                Type::create()
+                    .as_enum(AuthTokenKind::Enum)
+                    .values([
+                        AuthTokenKind::EmailVerification,
+                        AuthTokenKind::EmailChange,
+                        AuthTokenKind::PasswordReset,
+                    ])
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(AuthToken::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(AuthToken::Id).uuid().not_null().primary_key())
+                    .col(ColumnDef::new(AuthToken::UserId).uuid().not_null())
+                    .col(ColumnDef::new(AuthToken::Kind).custom(AuthTokenKind::Enum).not_null())
+                    .col(ColumnDef::new(AuthToken::TokenHash).string().unique_key().not_null())
+                    .col(ColumnDef::new(AuthToken::Payload).string())
+                    .col(ColumnDef::new(AuthToken::ExpiresAt).timestamp_with_time_zone().not_null())
+                    .col(ColumnDef::new(AuthToken::ConsumedAt).timestamp_with_time_zone())
+                    .col(ColumnDef::new(AuthToken::CreatedAt)
+                        .timestamp_with_time_zone()
+                        .not_null()
+                        .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-auth_token-user_id")
+                            .from(AuthToken::Table, AuthToken::UserId)
+                            .to(User::Table, User::Uuid)
+                            .on_delete(ForeignKeyAction::Cascade)
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(User::Table)
+                    .add_column(ColumnDef::new(User::EmailVerifiedAt).timestamp_with_time_zone())
+                    .to_owned(),
+            )
+            .await?;
+
+        //addresses of provider accounts were verified by the provider
+        manager
+            .get_connection()
+            .execute_unprepared(
+                r#"UPDATE "user" SET "email_verified_at" = CURRENT_TIMESTAMP
+                   WHERE "uuid" IN (SELECT "user_id" FROM "user_identity")"#,
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(User::Table)
+                    .drop_column(User::EmailVerifiedAt)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(AuthToken::Table).to_owned())
+            .await?;
+
+        manager
+            .drop_type(Type::drop().name(AuthTokenKind::Enum).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum AuthToken {
+    Table,
+    Id,
+    UserId,
+    Kind,
+    TokenHash,
+    Payload,
+    ExpiresAt,
+    ConsumedAt,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum AuthTokenKind {
+    #[sea_orm(iden = "auth_token_kind")]
+    Enum,
+    EmailVerification,
+    EmailChange,
+    PasswordReset,
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    Uuid,
+    EmailVerifiedAt,
+}
@@ -0,0 +1,136 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(TotpCredential::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(TotpCredential::UserId).uuid().not_null().primary_key())
+                    .col(ColumnDef::new(TotpCredential::Secret).string().not_null())
+                    //set once the first code was verified, unconfirmed secrets are not enforced
+                    .col(ColumnDef::new(TotpCredential::ConfirmedAt).timestamp_with_time_zone())
+                    .col(ColumnDef::new(TotpCredential::LastUsedStep).big_integer())
+                    .col(ColumnDef::new(TotpCredential::FailedAttempts)
+                        .integer()
+                        .not_null()
+                        .default(0),
+                    )
+                    .col(ColumnDef::new(TotpCredential::LockedUntil).timestamp_with_time_zone())
+                    .col(ColumnDef::new(TotpCredential::CreatedAt)
+                        .timestamp_with_time_zone()
+                        .not_null()
+                        .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-totp_credential-user_id")
+                            .from(TotpCredential::Table, TotpCredential::UserId)
+                            .to(User::Table, User::Uuid)
+                            .on_delete(ForeignKeyAction::Cascade)
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(RecoveryCode::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(RecoveryCode::Id).uuid().not_null().primary_key())
+                    .col(ColumnDef::new(RecoveryCode::UserId).uuid().not_null())
+                    .col(ColumnDef::new(RecoveryCode::CodeHash).string().not_null().unique_key())
+                    .col(ColumnDef::new(RecoveryCode::UsedAt).timestamp_with_time_zone())
+                    .col(ColumnDef::new(RecoveryCode::CreatedAt)
+                        .timestamp_with_time_zone()
+                        .not_null()
+                        .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-recovery_code-user_id")
+                            .from(RecoveryCode::Table, RecoveryCode::UserId)
+                            .to(User::Table, User::Uuid)
+                            .on_delete(ForeignKeyAction::Cascade)
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        //sessions of users with a second factor start out half authenticated
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Session::Table)
+                    .add_column(
+                        ColumnDef::new(Session::MfaPending)
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Session::Table)
+                    .drop_column(Session::MfaPending)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(RecoveryCode::Table).to_owned())
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(TotpCredential::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum TotpCredential {
+    Table,
+    UserId,
+    Secret,
+    ConfirmedAt,
+    LastUsedStep,
+    FailedAttempts,
+    LockedUntil,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum RecoveryCode {
+    Table,
+    Id,
+    UserId,
+    CodeHash,
+    UsedAt,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum Session {
+    Table,
+    MfaPending,
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    Uuid,
+}
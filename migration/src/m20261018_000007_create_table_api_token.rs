@@ -0,0 +1,65 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(ApiToken::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(ApiToken::Id).uuid().not_null().primary_key())
+                    .col(ColumnDef::new(ApiToken::UserId).uuid().not_null())
+                    .col(ColumnDef::new(ApiToken::Name).string().not_null())
+                    .col(ColumnDef::new(ApiToken::TokenHash).string().not_null().unique_key())
+                    //space separated, e.g. "blog:write user:write"
+                    .col(ColumnDef::new(ApiToken::Scopes).string().not_null())
+                    .col(ColumnDef::new(ApiToken::LastUsedAt).timestamp_with_time_zone())
+                    .col(ColumnDef::new(ApiToken::ExpiresAt).timestamp_with_time_zone())
+                    .col(ColumnDef::new(ApiToken::CreatedAt)
+                        .timestamp_with_time_zone()
+                        .not_null()
+                        .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-api_token-user_id")
+                            .from(ApiToken::Table, ApiToken::UserId)
+                            .to(User::Table, User::Uuid)
+                            .on_delete(ForeignKeyAction::Cascade)
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+
+        manager
+            .drop_table(Table::drop().table(ApiToken::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum ApiToken {
+    Table,
+    Id,
+    UserId,
+    Name,
+    TokenHash,
+    Scopes,
+    LastUsedAt,
+    ExpiresAt,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    Uuid,
+}
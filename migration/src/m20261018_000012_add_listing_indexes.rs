@@ -0,0 +1,134 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Blog::Table)
+                    .add_column(
+                        ColumnDef::new(Blog::Tags)
+                            .array(ColumnType::Text)
+                            .not_null()
+                            .default(Expr::cust("'{}'")),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        //users had no creation time, existing ones are ordered by their uuid among themselves
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(User::Table)
+                    .add_column(
+                        ColumnDef::new(User::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        //lists are paged by (created_at, id), in both directions
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-blog-created_at-id")
+                    .table(Blog::Table)
+                    .col(Blog::CreatedAt)
+                    .col(Blog::Id)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-blog-user_id-created_at-id")
+                    .table(Blog::Table)
+                    .col(Blog::UserId)
+                    .col(Blog::CreatedAt)
+                    .col(Blog::Id)
+                    .to_owned(),
+            )
+            .await?;
+
+        //GIN, so `tags @> ARRAY[tag]` does not scan the table
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-blog-tags")
+                    .table(Blog::Table)
+                    .col(Blog::Tags)
+                    .full_text()
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-user-created_at-uuid")
+                    .table(User::Table)
+                    .col(User::CreatedAt)
+                    .col(User::Uuid)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+
+        for (name, table) in [
+            ("idx-user-created_at-uuid", User::Table.into_iden()),
+            ("idx-blog-tags", Blog::Table.into_iden()),
+            ("idx-blog-user_id-created_at-id", Blog::Table.into_iden()),
+            ("idx-blog-created_at-id", Blog::Table.into_iden()),
+        ] {
+            manager
+                .drop_index(Index::drop().name(name).table(table).to_owned())
+                .await?;
+        }
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(User::Table)
+                    .drop_column(User::CreatedAt)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Blog::Table)
+                    .drop_column(Blog::Tags)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Blog {
+    Table,
+    Id,
+    UserId,
+    CreatedAt,
+    Tags,
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    Uuid,
+    CreatedAt,
+}
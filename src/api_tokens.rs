@@ -0,0 +1,114 @@
+use std::fmt;
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{Duration, Utc};
+use entity::{api_token, user};
+use migration::sea_orm::{
+    sea_query::Expr, ColumnTrait, Condition, DatabaseConnection, DbErr, EntityTrait, QueryFilter,
+};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Prefix of personal access tokens, makes leaked tokens easy to recognize in logs and scanners.
+const TOKEN_PREFIX: &str = "bp_";
+
+/// What a personal access token may be used for.
+///
+/// Routers opt in to token access by carrying the scope as an `Extension`, all other
+/// authenticated routes only accept the session cookie.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ApiScope {
+    #[serde(rename = "blog:write")]
+    BlogWrite,
+    #[serde(rename = "user:write")]
+    UserWrite,
+}
+
+impl ApiScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApiScope::BlogWrite => "blog:write",
+            ApiScope::UserWrite => "user:write",
+        }
+    }
+
+    fn parse(scope: &str) -> Option<ApiScope> {
+        match scope {
+            "blog:write" => Some(ApiScope::BlogWrite),
+            "user:write" => Some(ApiScope::UserWrite),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ApiScope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+pub fn join_scopes(scopes: &[ApiScope]) -> String {
+    scopes.iter().map(ApiScope::as_str).collect::<Vec<_>>().join(" ")
+}
+
+pub fn split_scopes(scopes: &str) -> Vec<ApiScope> {
+    scopes.split_whitespace().filter_map(ApiScope::parse).collect()
+}
+
+/// Creates a new random token, returns it in clear text together with the hash to store.
+pub fn generate_token() -> (String, String) {
+    let mut secret = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut secret);
+
+    let token = format!("{}{}", TOKEN_PREFIX, URL_SAFE_NO_PAD.encode(secret));
+    let hash = token_hash(&token);
+    (token, hash)
+}
+
+fn token_hash(token: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Resolves the owner and scopes of a bearer token. Unknown and expired tokens give `None`.
+///
+/// `last_used_at` is refreshed at most once a minute to keep busy CI jobs from writing on
+/// every request.
+pub async fn authenticate_token(
+    db: &DatabaseConnection,
+    token: &str,
+) -> Result<Option<(user::Model, Vec<ApiScope>)>, DbErr> {
+    if !token.starts_with(TOKEN_PREFIX) {
+        return Ok(None);
+    }
+
+    let hash = token_hash(token);
+    let now = Utc::now();
+
+    let Some((token, Some(user))) = api_token::Entity::find()
+        .filter(api_token::Column::TokenHash.eq(hash))
+        .filter(
+            Condition::any()
+                .add(api_token::Column::ExpiresAt.is_null())
+                .add(api_token::Column::ExpiresAt.gt(now)),
+        )
+        .find_also_related(user::Entity)
+        .one(db)
+        .await?
+    else {
+        return Ok(None);
+    };
+
+    api_token::Entity::update_many()
+        .col_expr(api_token::Column::LastUsedAt, Expr::value(now))
+        .filter(api_token::Column::Id.eq(token.id))
+        .filter(
+            Condition::any()
+                .add(api_token::Column::LastUsedAt.is_null())
+                .add(api_token::Column::LastUsedAt.lt(now - Duration::minutes(1))),
+        )
+        .exec(db)
+        .await?;
+
+    Ok(Some((user, split_scopes(&token.scopes))))
+}
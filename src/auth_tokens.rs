@@ -0,0 +1,156 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{Duration, Utc};
+use entity::{auth_token, sea_orm_active_enums::AuthTokenKind};
+use hmac::{Hmac, Mac};
+use migration::sea_orm::{
+    sea_query::Expr, ActiveEnum, ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr, EntityTrait,
+    QueryFilter, Set,
+};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::config::Config;
+
+type HmacSha256 = Hmac<Sha256>;
+
+fn signature(config: &Config, kind: AuthTokenKind, body: &str) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(config.auth.token_signing_key.as_bytes())
+        .expect("HMAC accepts any key length");
+    mac.update(kind.to_value().as_bytes());
+    mac.update(b".");
+    mac.update(body.as_bytes());
+    mac
+}
+
+/// Only the SHA-256 of a token is stored, a leaked table can't be used to forge links.
+fn token_hash(token: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Creates a single-use token for `kind` and returns it in clear text.
+///
+/// The token is random, signed with `TOKEN_SIGNING_KEY` for its kind and stored hashed
+/// with its expiry. `payload` carries extra data such as the new address of an email change.
+pub async fn issue_token(
+    db: &DatabaseConnection,
+    config: &Config,
+    user_id: Uuid,
+    kind: AuthTokenKind,
+    payload: Option<String>,
+    ttl: Duration,
+) -> Result<String, DbErr> {
+    let mut secret = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut secret);
+
+    let body = URL_SAFE_NO_PAD.encode(secret);
+    let sig = URL_SAFE_NO_PAD.encode(signature(config, kind, &body).finalize().into_bytes());
+    let token = format!("{body}.{sig}");
+
+    auth_token::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        user_id: Set(user_id),
+        kind: Set(kind),
+        token_hash: Set(token_hash(&token)),
+        payload: Set(payload),
+        expires_at: Set((Utc::now() + ttl).into()),
+        consumed_at: Set(None),
+        ..Default::default()
+    }
+    .insert(db)
+    .await?;
+
+    Ok(token)
+}
+
+/// Redeems a token of `kind`. Returns `None` for forged, unknown, expired or already used
+/// tokens. Marking the token consumed is a single conditional update, so two concurrent
+/// requests can never both redeem it.
+pub async fn consume_token(
+    db: &DatabaseConnection,
+    config: &Config,
+    token: &str,
+    kind: AuthTokenKind,
+) -> Result<Option<auth_token::Model>, DbErr> {
+    let Some((body, sig)) = token.split_once('.') else {
+        return Ok(None);
+    };
+    let Ok(sig) = URL_SAFE_NO_PAD.decode(sig) else {
+        return Ok(None);
+    };
+    if signature(config, kind, body).verify_slice(&sig).is_err() {
+        return Ok(None);
+    }
+
+    let hash = token_hash(token);
+    let now = Utc::now();
+
+    let result = auth_token::Entity::update_many()
+        .col_expr(auth_token::Column::ConsumedAt, Expr::value(now))
+        .filter(auth_token::Column::TokenHash.eq(hash.clone()))
+        .filter(auth_token::Column::Kind.eq(kind))
+        .filter(auth_token::Column::ConsumedAt.is_null())
+        .filter(auth_token::Column::ExpiresAt.gt(now))
+        .exec(db)
+        .await?;
+
+    if result.rows_affected == 0 {
+        return Ok(None);
+    }
+
+    auth_token::Entity::find()
+        .filter(auth_token::Column::TokenHash.eq(hash))
+        .one(db)
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signed(config: &Config, kind: AuthTokenKind, body: &str) -> String {
+        let sig = URL_SAFE_NO_PAD.encode(signature(config, kind, body).finalize().into_bytes());
+        format!("{body}.{sig}")
+    }
+
+    // A disconnected database panics on the first query, so `Ok(None)` shows the token was
+    // refused before it was looked up.
+    async fn consume(config: &Config, token: &str, kind: AuthTokenKind) -> Result<Option<auth_token::Model>, DbErr> {
+        consume_token(&DatabaseConnection::Disconnected, config, token, kind).await
+    }
+
+    #[tokio::test]
+    async fn refuses_malformed_and_forged_tokens() {
+        let config = Config::for_tests();
+        let body = URL_SAFE_NO_PAD.encode([7u8; 32]);
+        let valid = signed(&config, AuthTokenKind::PasswordReset, &body);
+        let (_, sig) = valid.split_once('.').unwrap();
+
+        let tokens = [
+            body.clone(),
+            format!("{body}.not base64!"),
+            format!("{body}.{}", URL_SAFE_NO_PAD.encode([0u8; 32])),
+            format!("{}.{sig}", URL_SAFE_NO_PAD.encode([8u8; 32])),
+        ];
+        for token in tokens {
+            assert!(
+                matches!(consume(&config, &token, AuthTokenKind::PasswordReset).await, Ok(None)),
+                "{}",
+                token
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn signature_is_bound_to_the_kind_and_the_key() {
+        let config = Config::for_tests();
+        let body = URL_SAFE_NO_PAD.encode([7u8; 32]);
+        let token = signed(&config, AuthTokenKind::EmailVerification, &body);
+
+        assert!(matches!(consume(&config, &token, AuthTokenKind::PasswordReset).await, Ok(None)));
+
+        let mut other_key = Config::for_tests();
+        other_key.auth.token_signing_key = "another-signing-key".to_string();
+        assert!(matches!(consume(&other_key, &token, AuthTokenKind::EmailVerification).await, Ok(None)));
+    }
+}
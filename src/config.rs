@@ -0,0 +1,508 @@
+use std::{collections::HashMap, env, fmt, fs, path::Path, str::FromStr, time::Duration};
+
+use http::HeaderValue;
+use lettre::message::Mailbox;
+use reqwest::Url;
+
+use crate::session_store::SessionLifetime;
+
+/// Every setting of the app, loaded and validated once at startup.
+///
+/// Values come from the environment, an optional TOML file fills in what the environment
+/// leaves out. The file is `CONFIG_FILE` or `config.toml` in the working directory; nested
+/// tables are flattened into the variable names, so `[session] idle_timeout_minutes = 30`
+/// is the same as `SESSION_IDLE_TIMEOUT_MINUTES=30`.
+pub struct Config {
+    pub database_url: String,
+    pub bind_address: String,
+    /// The externally reachable address of the app, used for OAuth callbacks, links in
+    /// mails and as the JWT issuer. Never ends with a slash.
+    pub public_base_url: String,
+    pub cors_origins: Vec<HeaderValue>,
+    /// Whether a proxy in front sets `X-Forwarded-For`.
+    pub trust_forwarded_for: bool,
+    pub redis_url: String,
+    pub session: SessionConfig,
+    pub jobs: JobsConfig,
+    pub mail: MailConfig,
+    pub storage: StorageConfig,
+    pub auth: AuthConfig,
+    pub providers: Vec<ProviderConfig>,
+}
+
+pub struct SessionConfig {
+    pub store: SessionBackend,
+    pub lifetime: SessionLifetime,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SessionBackend {
+    /// Postgres behind a Redis cache.
+    Cached,
+    Postgres,
+    Redis,
+    Memory,
+}
+
+pub struct JobsConfig {
+    pub session_sweep_interval: Duration,
+    pub token_sweep_interval: Duration,
+}
+
+pub enum MailConfig {
+    Smtp(SmtpConfig),
+    File { dir: String },
+    Memory,
+}
+
+pub struct SmtpConfig {
+    pub host: String,
+    pub port: Option<u16>,
+    pub credentials: Option<(String, String)>,
+    pub from: Mailbox,
+}
+
+pub struct StorageConfig {
+    pub s3_bucket: String,
+}
+
+pub struct AuthConfig {
+    /// Signs the links of verification, email change and reset mails.
+    pub token_signing_key: String,
+    /// The first key signs access tokens, all of them verify. Empty disables the token login.
+    pub jwt_signing_keys: Vec<JwtSigningKey>,
+}
+
+pub struct JwtSigningKey {
+    pub kid: String,
+    pub secret: String,
+}
+
+/// Which ID token claim is used as the display name of new users.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NameClaim {
+    Name,
+    PreferredUsername,
+}
+
+pub struct ProviderConfig {
+    pub name: String,
+    pub client_id: String,
+    pub client_secret: String,
+    /// Required for generic OpenID Connect providers, `google` and `gitlab` have defaults.
+    pub issuer_url: Option<String>,
+    pub display_name: Option<String>,
+    pub scopes: Option<String>,
+    pub name_claim: NameClaim,
+}
+
+/// Every problem found while loading, reported together so a broken deployment can be
+/// fixed in one go.
+#[derive(Debug)]
+pub struct ConfigError(pub Vec<String>);
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid configuration:")?;
+        for error in &self.0 {
+            write!(f, "\n  - {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// The raw values by variable name, collecting errors while they are parsed.
+struct Source {
+    values: HashMap<String, String>,
+    errors: Vec<String>,
+}
+
+fn flatten(prefix: &str, table: &toml::Table, values: &mut HashMap<String, String>) {
+    for (key, value) in table {
+        let key = if prefix.is_empty() {
+            key.to_uppercase()
+        } else {
+            format!("{}_{}", prefix, key.to_uppercase())
+        };
+
+        let value = match value {
+            toml::Value::Table(table) => {
+                flatten(&key, table, values);
+                continue;
+            }
+            toml::Value::String(value) => value.clone(),
+            toml::Value::Array(items) => items
+                .iter()
+                .map(|item| match item {
+                    toml::Value::String(item) => item.clone(),
+                    item => item.to_string(),
+                })
+                .collect::<Vec<_>>()
+                .join(","),
+            value => value.to_string(),
+        };
+        values.insert(key, value);
+    }
+}
+
+impl Source {
+    fn load() -> Source {
+        let mut source = Source {
+            values: HashMap::new(),
+            errors: Vec::new(),
+        };
+
+        let file = match env::var("CONFIG_FILE") {
+            Ok(file) => Some(file),
+            Err(_) => Path::new("config.toml").exists().then(|| "config.toml".to_string()),
+        };
+
+        if let Some(file) = file {
+            match fs::read_to_string(&file).map_err(|err| err.to_string()).and_then(|content| {
+                content.parse::<toml::Table>().map_err(|err| err.to_string())
+            }) {
+                Ok(table) => flatten("", &table, &mut source.values),
+                Err(err) => source.errors.push(format!("{}: {}", file, err)),
+            }
+        }
+
+        // the environment wins over the file
+        source.values.extend(env::vars());
+        source
+    }
+
+    fn get(&self, key: &str) -> Option<String> {
+        self.values
+            .get(key)
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty())
+    }
+
+    fn or(&self, key: &str, default: &str) -> String {
+        self.get(key).unwrap_or_else(|| default.to_string())
+    }
+
+    fn required(&mut self, key: &str) -> String {
+        self.get(key).unwrap_or_else(|| {
+            self.errors.push(format!("{} must be set", key));
+            String::new()
+        })
+    }
+
+    fn parse<T: FromStr>(&mut self, key: &str, default: T) -> T {
+        match self.get(key) {
+            Some(value) => value.parse().unwrap_or_else(|_| {
+                self.errors.push(format!("{} has the invalid value '{}'", key, value));
+                default
+            }),
+            None => default,
+        }
+    }
+
+    /// A number between 1 and `max`, the bound keeps durations built from it from overflowing.
+    fn positive(&mut self, key: &str, default: i64, max: i64) -> i64 {
+        let value = self.parse(key, default);
+        if !(1..=max).contains(&value) {
+            self.errors.push(format!("{} must be a number between 1 and {}", key, max));
+            return default;
+        }
+        value
+    }
+
+    fn list(&self, key: &str, default: &str) -> Vec<String> {
+        self.or(key, default)
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+}
+
+impl Config {
+    /// Reads the configuration, failing with every problem found.
+    pub fn load() -> Result<Config, ConfigError> {
+        Config::from_source(Source::load())
+    }
+
+    /// A configuration from the given variables only, ignoring the environment and the
+    /// config file, e.g. for tests.
+    pub fn from_values(values: &[(&str, &str)]) -> Result<Config, ConfigError> {
+        Config::from_source(Source {
+            values: values
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+            errors: Vec::new(),
+        })
+    }
+
+    /// The smallest valid configuration, for tests.
+    #[cfg(test)]
+    pub(crate) fn for_tests() -> Config {
+        Config::from_values(&TEST_VALUES).expect("the test configuration is valid")
+    }
+
+    fn from_source(mut source: Source) -> Result<Config, ConfigError> {
+
+        let public_base_url = source
+            .or("PUBLIC_BASE_URL", "http://localhost:3010")
+            .trim_end_matches('/')
+            .to_string();
+        if Url::parse(&public_base_url).is_err() {
+            source.errors.push(format!("PUBLIC_BASE_URL '{}' is not a URL", public_base_url));
+        }
+
+        let cors_origins = source
+            .list("CORS_ORIGINS", &public_base_url)
+            .into_iter()
+            .filter_map(|origin| {
+                origin.parse::<HeaderValue>().ok().or_else(|| {
+                    source.errors.push(format!("CORS_ORIGINS contains the invalid origin '{}'", origin));
+                    None
+                })
+            })
+            .collect();
+
+        let redis_url = source.required("REDIS_URL");
+        if !redis_url.is_empty() && redis::Client::open(redis_url.as_str()).is_err() {
+            source.errors.push("REDIS_URL is not a valid redis URL".to_string());
+        }
+
+        let config = Config {
+            database_url: source.required("DATABASE_URL"),
+            bind_address: source.or("BIND_ADDRESS", "localhost:3010"),
+            cors_origins,
+            trust_forwarded_for: source.parse("TRUST_FORWARDED_FOR", false),
+            redis_url,
+            session: session_config(&mut source),
+            jobs: JobsConfig {
+                session_sweep_interval: minutes(source.positive("SESSION_SWEEP_INTERVAL_MINUTES", 15, MAX_INTERVAL_MINUTES)),
+                token_sweep_interval: minutes(source.positive("TOKEN_SWEEP_INTERVAL_MINUTES", 60, MAX_INTERVAL_MINUTES)),
+            },
+            mail: mail_config(&mut source),
+            storage: StorageConfig {
+                s3_bucket: source.required("AWS_S3_BUCKET"),
+            },
+            auth: auth_config(&mut source),
+            providers: provider_configs(&mut source),
+            public_base_url,
+        };
+
+        if source.errors.is_empty() {
+            Ok(config)
+        } else {
+            Err(ConfigError(source.errors))
+        }
+    }
+}
+
+#[cfg(test)]
+const TEST_VALUES: [(&str, &str); 6] = [
+    ("DATABASE_URL", "postgres://localhost/blog_test"),
+    ("REDIS_URL", "redis://localhost"),
+    ("AWS_S3_BUCKET", "blog-test"),
+    ("TOKEN_SIGNING_KEY", "test-signing-key"),
+    ("GOOGLE_OAUTH_CLIENT_ID", "test-client"),
+    ("GOOGLE_OAUTH_CLIENT_SECRET", "test-secret"),
+];
+
+/// A week, the longest interval between two job runs or session renewals.
+const MAX_INTERVAL_MINUTES: i64 = 7 * 24 * 60;
+
+fn minutes(minutes: i64) -> Duration {
+    Duration::from_secs(minutes as u64 * 60)
+}
+
+fn session_config(source: &mut Source) -> SessionConfig {
+    let store = match source.or("SESSION_STORE", "cached").as_str() {
+        "cached" => SessionBackend::Cached,
+        "postgres" => SessionBackend::Postgres,
+        "redis" => SessionBackend::Redis,
+        "memory" => SessionBackend::Memory,
+        other => {
+            source.errors.push(format!(
+                "SESSION_STORE must be cached, postgres, redis or memory, not '{}'",
+                other
+            ));
+            SessionBackend::Cached
+        }
+    };
+
+    let defaults = SessionLifetime::default();
+    let lifetime = SessionLifetime {
+        idle: chrono::Duration::minutes(source.positive(
+            "SESSION_IDLE_TIMEOUT_MINUTES",
+            defaults.idle.num_minutes(),
+            365 * 24 * 60,
+        )),
+        absolute: chrono::Duration::hours(source.positive(
+            "SESSION_ABSOLUTE_TIMEOUT_HOURS",
+            defaults.absolute.num_hours(),
+            365 * 24,
+        )),
+        remember_me: chrono::Duration::days(source.positive(
+            "SESSION_REMEMBER_ME_DAYS",
+            defaults.remember_me.num_days(),
+            365,
+        )),
+        renew_interval: chrono::Duration::minutes(source.positive(
+            "SESSION_RENEW_INTERVAL_MINUTES",
+            defaults.renew_interval.num_minutes(),
+            MAX_INTERVAL_MINUTES,
+        )),
+    };
+
+    SessionConfig { store, lifetime }
+}
+
+fn mail_config(source: &mut Source) -> MailConfig {
+    match source.or("MAIL_TRANSPORT", "file").as_str() {
+        "smtp" => {
+            let host = source.required("SMTP_HOST");
+            let port = source.get("SMTP_PORT").map(|_| source.parse("SMTP_PORT", 587));
+            let credentials = source.get("SMTP_USERNAME").zip(source.get("SMTP_PASSWORD"));
+
+            let from = source.required("MAIL_FROM");
+            let from = from.parse::<Mailbox>().unwrap_or_else(|_| {
+                if !from.is_empty() {
+                    source.errors.push(format!("MAIL_FROM '{}' is not a mail address", from));
+                }
+                Mailbox::new(None, "noreply@localhost".parse().expect("valid fallback address"))
+            });
+
+            MailConfig::Smtp(SmtpConfig {
+                host,
+                port,
+                credentials,
+                from,
+            })
+        }
+        "memory" => MailConfig::Memory,
+        "file" => MailConfig::File {
+            dir: source.or("MAIL_DROP_DIR", "./mail_outbox"),
+        },
+        other => {
+            source.errors.push(format!("MAIL_TRANSPORT must be smtp, file or memory, not '{}'", other));
+            MailConfig::Memory
+        }
+    }
+}
+
+fn auth_config(source: &mut Source) -> AuthConfig {
+    let jwt_signing_keys = source
+        .list("JWT_SIGNING_KEYS", "")
+        .into_iter()
+        .filter_map(|entry| match entry.split_once(':') {
+            Some((kid, secret)) if !kid.is_empty() && !secret.is_empty() => Some(JwtSigningKey {
+                kid: kid.to_string(),
+                secret: secret.to_string(),
+            }),
+            _ => {
+                source.errors.push("JWT_SIGNING_KEYS entries must look like kid:secret".to_string());
+                None
+            }
+        })
+        .collect();
+
+    AuthConfig {
+        token_signing_key: source.required("TOKEN_SIGNING_KEY"),
+        jwt_signing_keys,
+    }
+}
+
+/// Every provider listed in `AUTH_PROVIDERS` (default `google`) with its `<NAME>_*` settings.
+fn provider_configs(source: &mut Source) -> Vec<ProviderConfig> {
+    source
+        .list("AUTH_PROVIDERS", "google")
+        .into_iter()
+        .map(|name| {
+            let name = name.to_lowercase();
+            let key = |suffix: &str| format!("{}_{}", name.to_uppercase(), suffix);
+
+            let issuer_url = source.get(&key("OIDC_ISSUER_URL"));
+            match &issuer_url {
+                Some(url) if Url::parse(url).is_err() => {
+                    source.errors.push(format!("{} '{}' is not a URL", key("OIDC_ISSUER_URL"), url));
+                }
+                None if !matches!(name.as_str(), "google" | "gitlab" | "github") => {
+                    source.errors.push(format!("{} must be set", key("OIDC_ISSUER_URL")));
+                }
+                _ => {}
+            }
+
+            let name_claim = match source.get(&key("NAME_CLAIM")).as_deref() {
+                None | Some("name") => NameClaim::Name,
+                Some("preferred_username") => NameClaim::PreferredUsername,
+                Some(other) => {
+                    source.errors.push(format!(
+                        "{} must be name or preferred_username, not '{}'",
+                        key("NAME_CLAIM"),
+                        other
+                    ));
+                    NameClaim::Name
+                }
+            };
+
+            ProviderConfig {
+                client_id: source.required(&key("OAUTH_CLIENT_ID")),
+                client_secret: source.required(&key("OAUTH_CLIENT_SECRET")),
+                issuer_url,
+                display_name: source.get(&key("DISPLAY_NAME")),
+                scopes: source.get(&key("SCOPES")),
+                name_claim,
+                name,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with(extra: &[(&'static str, &'static str)]) -> Result<Config, ConfigError> {
+        let values: Vec<_> = TEST_VALUES.iter().chain(extra).copied().collect();
+        Config::from_values(&values)
+    }
+
+    #[test]
+    fn reports_every_missing_variable_together() {
+        let Err(ConfigError(errors)) = Config::from_values(&[]) else {
+            panic!("an empty configuration must not load");
+        };
+
+        for key in ["DATABASE_URL", "REDIS_URL", "AWS_S3_BUCKET", "TOKEN_SIGNING_KEY"] {
+            assert!(errors.iter().any(|error| error.starts_with(key)), "{} is not reported", key);
+        }
+    }
+
+    #[test]
+    fn rejects_lifetimes_that_would_overflow() {
+        let Err(ConfigError(errors)) = with(&[
+            ("SESSION_REMEMBER_ME_DAYS", "1000000000000"),
+            ("SESSION_IDLE_TIMEOUT_MINUTES", "9223372036854775807"),
+            ("SESSION_SWEEP_INTERVAL_MINUTES", "0"),
+        ]) else {
+            panic!("oversized lifetimes must not load");
+        };
+
+        assert_eq!(errors.len(), 3, "{:?}", errors);
+    }
+
+    #[test]
+    fn accepts_lifetimes_within_bounds() {
+        let config = with(&[("SESSION_REMEMBER_ME_DAYS", "365")]).expect("valid configuration");
+
+        assert_eq!(config.session.lifetime.remember_me, chrono::Duration::days(365));
+    }
+
+    #[test]
+    fn strips_the_trailing_slash_of_the_base_url() {
+        let config = with(&[("PUBLIC_BASE_URL", "https://blog.example.com/")]).expect("valid configuration");
+
+        assert_eq!(config.public_base_url, "https://blog.example.com");
+    }
+}
@@ -0,0 +1,192 @@
+use std::{error::Error, fmt};
+
+use aws_sdk_s3::error::SdkError;
+use axum::{
+    http::{header, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use migration::sea_orm::{DbErr, SqlErr};
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::mailer::MailError;
+use crate::session_store::SessionError;
+use crate::storage::StorageError;
+
+tokio::task_local! {
+    /// The id of the request being handled, set by the `request_id` middleware.
+    pub static REQUEST_ID: String;
+}
+
+/// The request id of the current task, if it runs inside the `request_id` middleware.
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.try_with(Clone::clone).ok()
+}
+
+/// A single invalid field of a request body.
+#[derive(Clone, Debug, Serialize, PartialEq, Eq)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: impl Into<String>, message: impl Into<String>) -> Self {
+        FieldError {
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+}
+
+/// Every way a JSON handler can fail.
+///
+/// Answers with an RFC 7807 `application/problem+json` body carrying the request id, so a
+/// report from a client can be matched with the server log. The details of internal errors
+/// are only logged, never sent.
+#[derive(Debug)]
+pub enum AppError {
+    BadRequest(String),
+    Unauthorized(String),
+    /// Like `Unauthorized`, but a second factor code would let the request through.
+    SecondFactorRequired,
+    Forbidden(String),
+    NotFound(String),
+    Conflict(String),
+    /// The `If-Match` of the request names an outdated version of the resource.
+    PreconditionFailed(String),
+    Validation(Vec<FieldError>),
+    TooManyRequests(String),
+    /// A service the request depends on, e.g. the mail relay, failed.
+    BadGateway(String),
+    Unavailable(String),
+    Internal(Box<dyn Error + Send + Sync>),
+}
+
+pub type AppResult<T> = Result<T, AppError>;
+
+impl AppError {
+    pub fn internal(err: impl Into<Box<dyn Error + Send + Sync>>) -> Self {
+        AppError::Internal(err.into())
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            AppError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            AppError::Unauthorized(_) | AppError::SecondFactorRequired => StatusCode::UNAUTHORIZED,
+            AppError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::Conflict(_) => StatusCode::CONFLICT,
+            AppError::PreconditionFailed(_) => StatusCode::PRECONDITION_FAILED,
+            AppError::Validation(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::TooManyRequests(_) => StatusCode::TOO_MANY_REQUESTS,
+            AppError::BadGateway(_) => StatusCode::BAD_GATEWAY,
+            AppError::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn detail(&self) -> &str {
+        match self {
+            AppError::BadRequest(detail)
+            | AppError::Unauthorized(detail)
+            | AppError::Forbidden(detail)
+            | AppError::NotFound(detail)
+            | AppError::Conflict(detail)
+            | AppError::PreconditionFailed(detail)
+            | AppError::TooManyRequests(detail)
+            | AppError::BadGateway(detail)
+            | AppError::Unavailable(detail) => detail,
+            AppError::SecondFactorRequired => "Second factor required",
+            AppError::Validation(_) => "The request has invalid fields",
+            AppError::Internal(_) => "Internal server error",
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::Internal(err) => write!(f, "internal error: {}", err),
+            other => write!(f, "{}: {}", other.status(), other.detail()),
+        }
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        let request_id = current_request_id();
+
+        if let AppError::Internal(err) = &self {
+            tracing::error!("Request {} failed: {}", request_id.as_deref().unwrap_or("-"), err);
+        }
+
+        let mut problem = json!({
+            "type": "about:blank",
+            "title": status.canonical_reason().unwrap_or_default(),
+            "status": status.as_u16(),
+            "detail": self.detail(),
+            "request_id": request_id,
+        });
+        match &self {
+            AppError::Validation(errors) => problem["errors"] = json!(errors),
+            AppError::SecondFactorRequired => problem["mfa_required"] = Value::Bool(true),
+            _ => {}
+        }
+
+        let mut response = (status, Json(problem)).into_response();
+        response.headers_mut().insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/problem+json"),
+        );
+        response
+    }
+}
+
+impl From<DbErr> for AppError {
+    fn from(err: DbErr) -> Self {
+        match (&err, err.sql_err()) {
+            (DbErr::RecordNotFound(_), _) => AppError::NotFound("Not found".to_string()),
+            (_, Some(SqlErr::UniqueConstraintViolation(_))) => {
+                AppError::Conflict("Already exists".to_string())
+            }
+            _ => AppError::internal(err),
+        }
+    }
+}
+
+impl From<redis::RedisError> for AppError {
+    fn from(err: redis::RedisError) -> Self {
+        AppError::internal(err)
+    }
+}
+
+impl<E, R> From<SdkError<E, R>> for AppError
+where
+    E: Error + Send + Sync + 'static,
+    R: fmt::Debug + Send + Sync + 'static,
+{
+    fn from(err: SdkError<E, R>) -> Self {
+        AppError::internal(err)
+    }
+}
+
+impl From<StorageError> for AppError {
+    fn from(err: StorageError) -> Self {
+        AppError::internal(err)
+    }
+}
+
+impl From<SessionError> for AppError {
+    fn from(err: SessionError) -> Self {
+        AppError::internal(err)
+    }
+}
+
+impl From<MailError> for AppError {
+    fn from(err: MailError) -> Self {
+        AppError::internal(err)
+    }
+}
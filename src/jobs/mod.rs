@@ -0,0 +1,80 @@
+use std::{error::Error, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use migration::sea_orm::DatabaseConnection;
+use tokio::{
+    task::JoinHandle,
+    time::{self, MissedTickBehavior},
+};
+
+use crate::config::JobsConfig;
+use crate::session_store::SessionStore;
+
+pub mod sweep;
+
+pub use sweep::{AuthTokenSweep, RefreshTokenSweep, SessionSweep};
+
+pub type JobError = Box<dyn Error + Send + Sync>;
+
+/// A maintenance task that runs periodically inside the server process.
+#[async_trait]
+pub trait Job: Send + Sync {
+    /// Shown in the logs.
+    fn name(&self) -> &'static str;
+
+    /// Runs the task once and returns how many items it cleaned up.
+    async fn run(&self) -> Result<u64, JobError>;
+}
+
+/// Runs every registered job on its own interval.
+///
+/// Each job runs once at startup and then every interval. A run that takes longer than the
+/// interval delays the next one instead of piling up, and failed runs are logged and tried
+/// again on the next tick.
+#[derive(Default)]
+pub struct JobRunner {
+    jobs: Vec<(Duration, Arc<dyn Job>)>,
+}
+
+impl JobRunner {
+    pub fn new() -> Self {
+        JobRunner::default()
+    }
+
+    pub fn register(mut self, interval: Duration, job: impl Job + 'static) -> Self {
+        self.jobs.push((interval, Arc::new(job)));
+        self
+    }
+
+    pub fn start(self) -> Vec<JoinHandle<()>> {
+        self.jobs
+            .into_iter()
+            .map(|(interval, job)| {
+                tracing::info!("Scheduling job {} every {}s", job.name(), interval.as_secs());
+                tokio::spawn(async move {
+                    let mut ticker = time::interval(interval);
+                    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                    loop {
+                        ticker.tick().await;
+                        match job.run().await {
+                            Ok(count) => tracing::info!("Job {} finished, {} removed", job.name(), count),
+                            Err(err) => tracing::error!("Job {} failed: {}", job.name(), err),
+                        }
+                    }
+                })
+            })
+            .collect()
+    }
+}
+
+/// The built-in cleanup jobs on their configured intervals.
+pub fn maintenance_jobs(
+    config: &JobsConfig,
+    db: Arc<DatabaseConnection>,
+    sessions: Arc<dyn SessionStore>,
+) -> JobRunner {
+    JobRunner::new()
+        .register(config.session_sweep_interval, SessionSweep::new(sessions))
+        .register(config.token_sweep_interval, AuthTokenSweep::new(db.clone()))
+        .register(config.token_sweep_interval, RefreshTokenSweep::new(db))
+}
@@ -0,0 +1,88 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::Utc;
+use entity::{auth_token, refresh_token};
+use migration::sea_orm::{ColumnTrait, Condition, DatabaseConnection, EntityTrait, QueryFilter};
+
+use super::{Job, JobError};
+use crate::session_store::SessionStore;
+
+/// Deletes sessions that expired without their browser coming back.
+pub struct SessionSweep {
+    sessions: Arc<dyn SessionStore>,
+}
+
+impl SessionSweep {
+    pub fn new(sessions: Arc<dyn SessionStore>) -> Self {
+        SessionSweep { sessions }
+    }
+}
+
+#[async_trait]
+impl Job for SessionSweep {
+    fn name(&self) -> &'static str {
+        "session_sweep"
+    }
+
+    async fn run(&self) -> Result<u64, JobError> {
+        Ok(self.sessions.delete_expired().await?)
+    }
+}
+
+/// Deletes verification, email change and reset tokens that were used or ran out.
+pub struct AuthTokenSweep {
+    db: Arc<DatabaseConnection>,
+}
+
+impl AuthTokenSweep {
+    pub fn new(db: Arc<DatabaseConnection>) -> Self {
+        AuthTokenSweep { db }
+    }
+}
+
+#[async_trait]
+impl Job for AuthTokenSweep {
+    fn name(&self) -> &'static str {
+        "auth_token_sweep"
+    }
+
+    async fn run(&self) -> Result<u64, JobError> {
+        let result = auth_token::Entity::delete_many()
+            .filter(
+                Condition::any()
+                    .add(auth_token::Column::ConsumedAt.is_not_null())
+                    .add(auth_token::Column::ExpiresAt.lte(Utc::now())),
+            )
+            .exec(self.db.as_ref())
+            .await?;
+        Ok(result.rows_affected)
+    }
+}
+
+/// Deletes expired refresh tokens. Used and revoked ones stay until they expire, reuse
+/// detection needs them.
+pub struct RefreshTokenSweep {
+    db: Arc<DatabaseConnection>,
+}
+
+impl RefreshTokenSweep {
+    pub fn new(db: Arc<DatabaseConnection>) -> Self {
+        RefreshTokenSweep { db }
+    }
+}
+
+#[async_trait]
+impl Job for RefreshTokenSweep {
+    fn name(&self) -> &'static str {
+        "refresh_token_sweep"
+    }
+
+    async fn run(&self) -> Result<u64, JobError> {
+        let result = refresh_token::Entity::delete_many()
+            .filter(refresh_token::Column::ExpiresAt.lte(Utc::now()))
+            .exec(self.db.as_ref())
+            .await?;
+        Ok(result.rows_affected)
+    }
+}
@@ -0,0 +1,62 @@
+use chrono::{Duration, Utc};
+use jsonwebtoken::{decode, decode_header, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::config::Config;
+
+pub const ACCESS_TOKEN_TTL_MINUTES: i64 = 15;
+
+#[derive(Serialize, Deserialize)]
+struct Claims {
+    sub: Uuid,
+    iss: String,
+    iat: i64,
+    exp: i64,
+}
+
+/// Whether `JWT_SIGNING_KEYS` lists any `kid:secret` pair.
+///
+/// The first key signs new tokens, all listed keys are accepted for verification. To rotate,
+/// put the new key in front and drop the old one once its tokens have expired. Without keys
+/// the token login is disabled.
+pub fn is_configured(config: &Config) -> bool {
+    !config.auth.jwt_signing_keys.is_empty()
+}
+
+/// Signs a short-lived access token for the user with the current key.
+pub fn issue_access_token(config: &Config, user_id: Uuid) -> Option<String> {
+    let key = config.auth.jwt_signing_keys.first()?;
+    let now = Utc::now();
+
+    let mut header = Header::new(Algorithm::HS256);
+    header.kid = Some(key.kid.clone());
+
+    let claims = Claims {
+        sub: user_id,
+        iss: config.public_base_url.clone(),
+        iat: now.timestamp(),
+        exp: (now + Duration::minutes(ACCESS_TOKEN_TTL_MINUTES)).timestamp(),
+    };
+
+    match encode(&header, &claims, &EncodingKey::from_secret(key.secret.as_bytes())) {
+        Ok(token) => Some(token),
+        Err(err) => {
+            tracing::error!("Failed to sign the access token: {:?}", err);
+            None
+        }
+    }
+}
+
+/// Returns the user of a valid access token signed by one of the configured keys.
+pub fn verify_access_token(config: &Config, token: &str) -> Option<Uuid> {
+    let kid = decode_header(token).ok()?.kid?;
+    let key = config.auth.jwt_signing_keys.iter().find(|key| key.kid == kid)?;
+
+    let mut validation = Validation::new(Algorithm::HS256);
+    validation.set_issuer(&[config.public_base_url.as_str()]);
+
+    decode::<Claims>(token, &DecodingKey::from_secret(key.secret.as_bytes()), &validation)
+        .ok()
+        .map(|data| data.claims.sub)
+}
@@ -1,20 +1,43 @@
-use std::sync::Arc;
+use std::net::SocketAddr;
 
-use migration::sea_orm::DatabaseConnection;
+use axum::Router;
 use tokio::net::TcpListener;
+
+use jobs::maintenance_jobs;
+use state::AppState;
+pub mod config;
+pub mod error;
 mod routes;
 mod models;
 mod redis_manager;
+mod policy;
+mod auth_tokens;
+mod lockout;
+mod api_tokens;
+mod jwt;
+pub mod jobs;
+pub mod mailer;
+pub mod session_store;
+pub mod state;
+pub mod storage;
+pub mod validation;
 
 
-pub async fn run(db : Arc<DatabaseConnection>) {
-    let app = routes::create_all_routes(db);
+/// The whole HTTP app on top of the given state, without binding a socket.
+pub fn app(state: AppState) -> Router {
+    routes::create_all_routes(state)
+}
 
-    let listener = TcpListener::bind("localhost:3010")
+pub async fn run(state: AppState) {
+    let listener = TcpListener::bind(&state.config().bind_address)
         .await
         .unwrap();
 
-    axum::serve(listener, app.await)
+    // runs next to the server until the process exits
+    maintenance_jobs(&state.config().jobs, state.db().clone(), state.sessions().clone()).start();
+
+    // the peer address is recorded with every new session
+    axum::serve(listener, app(state).into_make_service_with_connect_info::<SocketAddr>())
         .await
         .unwrap();
 }
\ No newline at end of file
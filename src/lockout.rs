@@ -0,0 +1,73 @@
+use chrono::{Duration, Utc};
+use migration::sea_orm::{
+    sea_query::{Expr, Keyword, SimpleExpr},
+    ColumnTrait, Condition, ConnectionTrait, DbErr, EntityTrait, ModelTrait, QueryFilter, Value,
+};
+use uuid::Uuid;
+
+/// The brute-force lockout of a credential table keyed by `user_id`.
+///
+/// An attempt is counted before the secret is checked, in a single UPDATE, so parallel
+/// guesses can't all read the same count: at most `max_failed_attempts` of them are let
+/// through before the row is locked for `duration`.
+pub struct Lockout<E: EntityTrait> {
+    pub user_id: E::Column,
+    pub failed_attempts: E::Column,
+    pub locked_until: E::Column,
+    pub max_failed_attempts: i32,
+    pub duration: Duration,
+}
+
+pub enum Attempt {
+    Allowed,
+    /// Allowed, but it used up the last try and locked the row.
+    LastBeforeLock,
+    Locked,
+}
+
+impl<E: EntityTrait> Lockout<E> {
+    pub async fn claim_attempt<C: ConnectionTrait>(&self, db: &C, user_id: Uuid) -> Result<Attempt, DbErr> {
+        let now = Utc::now();
+        let reaches_limit = Expr::col(self.failed_attempts).gte(self.max_failed_attempts - 1);
+
+        let claimed = E::update_many()
+            .col_expr(
+                self.failed_attempts,
+                Expr::case(reaches_limit.clone(), 0)
+                    .finally(Expr::col(self.failed_attempts).add(1))
+                    .into(),
+            )
+            .col_expr(
+                self.locked_until,
+                Expr::case(reaches_limit, Expr::value(now + self.duration))
+                    .finally(SimpleExpr::Keyword(Keyword::Null))
+                    .into(),
+            )
+            .filter(self.user_id.eq(user_id))
+            .filter(
+                Condition::any()
+                    .add(self.locked_until.is_null())
+                    .add(self.locked_until.lte(now)),
+            )
+            .exec_with_returning(db)
+            .await?;
+
+        // the counter starts over at the lock, so a claim that returns 0 is the one that locked
+        Ok(match claimed.first() {
+            None => Attempt::Locked,
+            Some(row) if row.get(self.failed_attempts) == Value::Int(Some(0)) => Attempt::LastBeforeLock,
+            Some(_) => Attempt::Allowed,
+        })
+    }
+
+    /// Forgets the failed attempts after the secret was right.
+    pub async fn reset<C: ConnectionTrait>(&self, db: &C, user_id: Uuid) -> Result<(), DbErr> {
+        E::update_many()
+            .col_expr(self.failed_attempts, Expr::value(0))
+            .col_expr(self.locked_until, SimpleExpr::Keyword(Keyword::Null))
+            .filter(self.user_id.eq(user_id))
+            .exec(db)
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,39 @@
+use std::path::PathBuf;
+
+use async_trait::async_trait;
+use chrono::Utc;
+use uuid::Uuid;
+
+use super::{Email, MailError, Mailer};
+
+/// Writes every mail as a file into a directory instead of sending it, handy for development.
+pub struct FileDropMailer {
+    dir: PathBuf,
+}
+
+impl FileDropMailer {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        FileDropMailer { dir: dir.into() }
+    }
+}
+
+#[async_trait]
+impl Mailer for FileDropMailer {
+    async fn send(&self, email: Email) -> Result<(), MailError> {
+        tokio::fs::create_dir_all(&self.dir)
+            .await
+            .map_err(|err| MailError(format!("{err}")))?;
+
+        let path = self
+            .dir
+            .join(format!("{}-{}.eml", Utc::now().format("%Y%m%dT%H%M%S"), Uuid::new_v4()));
+        let contents = format!("To: {}\r\nSubject: {}\r\n\r\n{}\r\n", email.to, email.subject, email.body);
+
+        tokio::fs::write(&path, contents)
+            .await
+            .map_err(|err| MailError(format!("{err}")))?;
+
+        tracing::info!("Mail to {} dropped at {}", email.to, path.display());
+        Ok(())
+    }
+}
@@ -0,0 +1,26 @@
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+
+use super::{Email, MailError, Mailer};
+
+/// Keeps sent mails in memory so tests can assert on them without a network.
+#[derive(Default)]
+pub struct InMemoryMailer {
+    sent: Mutex<Vec<Email>>,
+}
+
+impl InMemoryMailer {
+    /// All mails sent so far, oldest first.
+    pub fn sent(&self) -> Vec<Email> {
+        self.sent.lock().expect("mailer lock poisoned").clone()
+    }
+}
+
+#[async_trait]
+impl Mailer for InMemoryMailer {
+    async fn send(&self, email: Email) -> Result<(), MailError> {
+        self.sent.lock().expect("mailer lock poisoned").push(email);
+        Ok(())
+    }
+}
@@ -0,0 +1,47 @@
+use std::{fmt, sync::Arc};
+
+use async_trait::async_trait;
+
+use crate::config::MailConfig;
+
+pub mod file_drop;
+pub mod memory;
+pub mod smtp;
+
+pub use file_drop::FileDropMailer;
+pub use memory::InMemoryMailer;
+pub use smtp::SmtpMailer;
+
+/// A plain text email.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+#[derive(Debug)]
+pub struct MailError(pub String);
+
+impl fmt::Display for MailError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "failed to send mail: {}", self.0)
+    }
+}
+
+impl std::error::Error for MailError {}
+
+/// Delivers emails, e.g. verification links and password resets.
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, email: Email) -> Result<(), MailError>;
+}
+
+/// Builds the configured transport: `smtp`, `file` (default) or `memory`.
+pub fn mailer_from_config(config: &MailConfig) -> Arc<dyn Mailer> {
+    match config {
+        MailConfig::Smtp(smtp) => Arc::new(SmtpMailer::new(smtp)),
+        MailConfig::Memory => Arc::new(InMemoryMailer::default()),
+        MailConfig::File { dir } => Arc::new(FileDropMailer::new(dir)),
+    }
+}
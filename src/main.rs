@@ -1,21 +1,32 @@
-mod models;
-use std::{env, sync::Arc};
+use std::{process, sync::Arc};
 
-use blog_proj::run;
+use blog_proj::{config::Config, run, state::AppState};
 use dotenv::dotenv;
 use migration::sea_orm::Database;
+use tracing_subscriber::EnvFilter;
 
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     //init dotenv
     dotenv().ok();
-    let db_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-    let db_conn = Database::connect(&db_url).await?;
+
+    //RUST_LOG picks the log level, everything from info up by default
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")))
+        .init();
+
+    //every setting is checked up front, a broken deployment fails before serving anything
+    let config = Config::load().unwrap_or_else(|err| {
+        tracing::error!("{}", err);
+        process::exit(1);
+    });
+
+    let db_conn = Database::connect(&config.database_url).await?;
     let db_conn = Arc::new(db_conn);
 
-    run(db_conn).await;
+    let state = AppState::from_config(db_conn, config).await;
+    run(state).await;
 
     Ok(())
 }
-
@@ -1,14 +1,34 @@
 use chrono::{DateTime, FixedOffset};
+use entity::blog;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+use crate::routes::pagination::SortOrder;
+use crate::validation::{Validate, Validator, MAX_CONTENT_LENGTH, MAX_TITLE_LENGTH};
+
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct CreateBlogModel{
     pub title : String, 
     pub content : String, 
-    pub user_id : Uuid,
     pub images : Option<Vec<String>>,
+    pub tags : Option<Vec<String>>,
+}
+
+
+impl Validate for CreateBlogModel {
+    fn validate(&self, check: &mut Validator<'_>) {
+        check
+            .not_blank("title", &self.title)
+            .max_length("title", &self.title, MAX_TITLE_LENGTH)
+            .max_length("content", &self.content, MAX_CONTENT_LENGTH);
+        if let Some(images) = &self.images {
+            check.stored_files("images", images);
+        }
+        if let Some(tags) = &self.tags {
+            check.tags("tags", tags);
+        }
+    }
 }
 
 
@@ -18,16 +38,100 @@ pub struct UpdateBlogModel{
     pub content : String,
 }
 
+impl Validate for UpdateBlogModel {
+    fn validate(&self, check: &mut Validator<'_>) {
+        check
+            .not_blank("title", &self.title)
+            .max_length("title", &self.title, MAX_TITLE_LENGTH)
+            .max_length("content", &self.content, MAX_CONTENT_LENGTH);
+    }
+}
+
+/// The fields a `PATCH` changes, missing ones are kept.
+#[derive(Serialize, Deserialize)]
+pub struct PatchBlogModel{
+    pub title : Option<String>, 
+    pub content : Option<String>,
+    pub images : Option<Vec<String>>,
+    pub tags : Option<Vec<String>>,
+}
+
+impl Validate for PatchBlogModel {
+    fn validate(&self, check: &mut Validator<'_>) {
+        if self.title.is_none()
+            && self.content.is_none()
+            && self.images.is_none()
+            && self.tags.is_none()
+        {
+            check.fail("body", "Must change at least one field");
+        }
+        if let Some(title) = &self.title {
+            check
+                .not_blank("title", title)
+                .max_length("title", title, MAX_TITLE_LENGTH);
+        }
+        if let Some(content) = &self.content {
+            check.max_length("content", content, MAX_CONTENT_LENGTH);
+        }
+        if let Some(images) = &self.images {
+            check.stored_files("images", images);
+        }
+        if let Some(tags) = &self.tags {
+            check.tags("tags", tags);
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct GetBlogModel{
+    pub id : i32,
     pub title : String, 
     pub content : String, 
     pub user_id : Uuid,
     pub created_at : DateTime<FixedOffset>,
+    pub updated_at : DateTime<FixedOffset>,
     pub images : Option<Vec<String>>,
+    pub tags : Vec<String>,
+}
+
+impl From<blog::Model> for GetBlogModel {
+    fn from(blog: blog::Model) -> Self {
+        GetBlogModel {
+            id: blog.id,
+            title: blog.title,
+            content: blog.content,
+            user_id: blog.user_id,
+            created_at: blog.created_at,
+            updated_at: blog.updated_at,
+            images: blog.images,
+            tags: blog.tags,
+        }
+    }
 }
 
 #[derive(Deserialize, Serialize, Default)]
 pub struct GetAllBlogsModel{
-    pub blogs : Vec<GetBlogModel>
+    pub blogs : Vec<GetBlogModel>,
+    /// Pass as `cursor` to get the next page, missing on the last one.
+    pub next_cursor : Option<String>,
+}
+
+/// Paging and filters of the blog lists, `from` and `to` bound `created_at` (inclusive and
+/// exclusive).
+#[derive(Deserialize, Serialize, Clone, Default)]
+pub struct BlogListQuery{
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit : Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cursor : Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub order : Option<SortOrder>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author : Option<Uuid>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from : Option<DateTime<FixedOffset>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub to : Option<DateTime<FixedOffset>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tag : Option<String>,
 }
@@ -1,17 +1,47 @@
+use entity::sea_orm_active_enums::Role;
+use chrono::{DateTime, FixedOffset, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+use crate::api_tokens::ApiScope;
+use crate::routes::pagination::SortOrder;
+use crate::validation::{Validate, Validator, MAX_NAME_LENGTH, MAX_PASSWORD_LENGTH};
 
-#[derive(Deserialize, Serialize, Clone)]
-pub struct UserModel{
-    pub name : String, 
-    pub email : String, 
-    pub uuid : Uuid, 
-}
 
 #[derive(Deserialize, Serialize)]
 pub struct UpdateUserModel{
     pub name : String,
+    pub role : Option<Role>,
+}
+
+impl Validate for UpdateUserModel {
+    fn validate(&self, check: &mut Validator<'_>) {
+        check
+            .not_blank("name", &self.name)
+            .max_length("name", &self.name, MAX_NAME_LENGTH);
+    }
+}
+
+
+/// The fields a `PATCH` changes, missing ones are kept. The email is changed through
+/// `POST /me/email`, which verifies the new address first.
+#[derive(Deserialize, Serialize)]
+pub struct PatchUserModel{
+    pub name : Option<String>,
+    pub role : Option<Role>,
+}
+
+impl Validate for PatchUserModel {
+    fn validate(&self, check: &mut Validator<'_>) {
+        if self.name.is_none() && self.role.is_none() {
+            check.fail("body", "Must change at least one field");
+        }
+        if let Some(name) = &self.name {
+            check
+                .not_blank("name", name)
+                .max_length("name", name, MAX_NAME_LENGTH);
+        }
+    }
 }
 
 
@@ -30,7 +60,21 @@ pub struct UserModelPub{
 
 #[derive(Deserialize, Serialize, Default)]
 pub struct GetAllUsersModel{
-    pub users : Vec<UserModelPub>
+    pub users : Vec<UserModelPub>,
+    /// Pass as `cursor` to get the next page, missing on the last one.
+    pub next_cursor : Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Default)]
+pub struct UserListQuery{
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit : Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cursor : Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub order : Option<SortOrder>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub role : Option<Role>,
 }
 
 #[derive(Deserialize, Serialize, Debug)]
@@ -40,5 +84,110 @@ pub struct CreateUserModel{
     pub password : String,
 }
 
+impl Validate for CreateUserModel {
+    fn validate(&self, check: &mut Validator<'_>) {
+        check
+            .not_blank("name", &self.name)
+            .max_length("name", &self.name, MAX_NAME_LENGTH)
+            .email("email", &self.email)
+            .password("password", &self.password);
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct LoginModel{
+    pub email : String, 
+    pub password : String,
+    #[serde(default)]
+    pub remember_me : bool,
+}
+
+/// `current_password` may only be left out by users that have no password yet.
+#[derive(Deserialize, Serialize)]
+pub struct ChangePasswordModel{
+    pub current_password : Option<String>, 
+    pub new_password : String,
+}
+
+impl Validate for ChangePasswordModel {
+    fn validate(&self, check: &mut Validator<'_>) {
+        check.password("new_password", &self.new_password);
+        if let Some(current_password) = &self.current_password {
+            check.max_length("current_password", current_password, MAX_PASSWORD_LENGTH);
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct ChangeEmailModel{
+    pub email : String,
+}
+
+impl Validate for ChangeEmailModel {
+    fn validate(&self, check: &mut Validator<'_>) {
+        check.email("email", &self.email);
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct ForgotPasswordModel{
+    pub email : String,
+}
 
+impl Validate for ForgotPasswordModel {
+    fn validate(&self, check: &mut Validator<'_>) {
+        check.email("email", &self.email);
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct ResetPasswordModel{
+    pub token : String, 
+    pub new_password : String,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct MfaCodeModel{
+    pub code : String,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct CreateApiTokenModel{
+    pub name : String, 
+    pub scopes : Vec<ApiScope>,
+    pub expires_in_days : Option<i64>,
+}
 
+#[derive(Deserialize, Serialize)]
+pub struct ApiTokenModel{
+    pub id : Uuid, 
+    pub name : String, 
+    pub scopes : Vec<ApiScope>,
+    pub last_used_at : Option<DateTime<FixedOffset>>,
+    pub expires_at : Option<DateTime<FixedOffset>>,
+    pub created_at : DateTime<FixedOffset>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct SessionModel{
+    pub id : Uuid, 
+    pub user_agent : Option<String>,
+    pub ip_address : Option<String>,
+    pub created_at : DateTime<Utc>,
+    pub last_seen_at : DateTime<Utc>,
+    pub expires_at : DateTime<Utc>,
+    pub remember_me : bool,
+    pub current : bool,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct TokenLoginModel{
+    pub email : String, 
+    pub password : String,
+    pub code : Option<String>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct RefreshTokenModel{
+    pub refresh_token : String,
+}
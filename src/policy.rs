@@ -0,0 +1,137 @@
+use entity::{blog, sea_orm_active_enums::Role, user};
+
+use crate::error::AppError;
+
+/// Everything a user can try to do to a blog post or a profile.
+#[derive(Debug, Clone, Copy)]
+pub enum Action {
+    CreateBlog,
+    UpdateBlog,
+    DeleteBlog,
+    UpdateUser,
+    ChangeRole,
+    RevokeSessions,
+}
+
+/// Returned when the policy refuses an action, turns into a 403 `AppError`.
+pub struct Forbidden;
+
+impl From<Forbidden> for AppError {
+    fn from(_: Forbidden) -> Self {
+        AppError::Forbidden("You have no rights".to_string())
+    }
+}
+
+
+fn is_allowed(actor: &user::Model, action: Action, owner: Option<uuid::Uuid>) -> bool {
+    let is_owner = owner == Some(actor.uuid);
+
+    match (actor.role, action) {
+        (Role::Admin, _) => true,
+        (Role::Editor, Action::CreateBlog | Action::UpdateBlog) => true,
+        (Role::Editor | Role::Author, Action::DeleteBlog) => is_owner,
+        (Role::Author, Action::CreateBlog) => true,
+        (Role::Author, Action::UpdateBlog) => is_owner,
+        (Role::Reader, Action::CreateBlog | Action::UpdateBlog | Action::DeleteBlog) => false,
+        (_, Action::UpdateUser) => is_owner,
+        (_, Action::ChangeRole | Action::RevokeSessions) => false,
+    }
+}
+
+fn check(actor: &user::Model, action: Action, owner: Option<uuid::Uuid>, target: &str) -> Result<(), Forbidden> {
+    if is_allowed(actor, action, owner) {
+        return Ok(());
+    }
+
+    tracing::warn!(
+        "Forbidden: user {} ({:?}) attempted {:?} on {}",
+        actor.uuid, actor.role, action, target
+    );
+    Err(Forbidden)
+}
+
+/// Readers may not publish, everybody else may.
+pub fn authorize_blog_create(actor: &user::Model) -> Result<(), Forbidden> {
+    check(actor, Action::CreateBlog, None, "new blog")
+}
+
+/// Owners may change their own posts, editors may update any post and admins may do anything.
+pub fn authorize_blog(actor: &user::Model, action: Action, blog: &blog::Model) -> Result<(), Forbidden> {
+    check(actor, action, Some(blog.user_id), &format!("blog {}", blog.id))
+}
+
+/// Users may only edit their own profile, admins may edit every profile, change roles and
+/// end the sessions of other users.
+pub fn authorize_user(actor: &user::Model, action: Action, target: &user::Model) -> Result<(), Forbidden> {
+    check(actor, action, Some(target.uuid), &format!("user {}", target.uuid))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    use super::*;
+
+    fn user(role: Role) -> user::Model {
+        user::Model {
+            name: "Test".to_string(),
+            email: "test@example.com".to_string(),
+            uuid: Uuid::new_v4(),
+            role,
+            email_verified_at: None,
+            version: 1,
+            updated_at: Utc::now().into(),
+            created_at: Utc::now().into(),
+        }
+    }
+
+    #[test]
+    fn readers_may_not_publish() {
+        let reader = user(Role::Reader);
+
+        for action in [Action::CreateBlog, Action::UpdateBlog, Action::DeleteBlog] {
+            assert!(!is_allowed(&reader, action, Some(reader.uuid)), "{:?}", action);
+        }
+    }
+
+    #[test]
+    fn authors_only_change_their_own_posts() {
+        let author = user(Role::Author);
+        let other = Uuid::new_v4();
+
+        assert!(is_allowed(&author, Action::CreateBlog, None));
+        assert!(is_allowed(&author, Action::UpdateBlog, Some(author.uuid)));
+        assert!(is_allowed(&author, Action::DeleteBlog, Some(author.uuid)));
+        assert!(!is_allowed(&author, Action::UpdateBlog, Some(other)));
+        assert!(!is_allowed(&author, Action::DeleteBlog, Some(other)));
+    }
+
+    #[test]
+    fn editors_update_any_post_but_delete_only_their_own() {
+        let editor = user(Role::Editor);
+        let other = Uuid::new_v4();
+
+        assert!(is_allowed(&editor, Action::UpdateBlog, Some(other)));
+        assert!(!is_allowed(&editor, Action::DeleteBlog, Some(other)));
+        assert!(is_allowed(&editor, Action::DeleteBlog, Some(editor.uuid)));
+    }
+
+    #[test]
+    fn only_admins_manage_other_users() {
+        let admin = user(Role::Admin);
+        let other = Uuid::new_v4();
+
+        for role in [Role::Reader, Role::Author, Role::Editor] {
+            let actor = user(role);
+            assert!(is_allowed(&actor, Action::UpdateUser, Some(actor.uuid)));
+            assert!(!is_allowed(&actor, Action::UpdateUser, Some(other)));
+            assert!(!is_allowed(&actor, Action::ChangeRole, Some(actor.uuid)));
+            assert!(!is_allowed(&actor, Action::RevokeSessions, Some(other)));
+        }
+
+        for action in [Action::UpdateUser, Action::ChangeRole, Action::RevokeSessions, Action::DeleteBlog] {
+            assert!(is_allowed(&admin, action, Some(other)), "{:?}", action);
+        }
+    }
+}
@@ -0,0 +1,45 @@
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+
+use super::pool::RedisPool;
+
+// the user has ten minutes to finish the round trip to the provider
+const PENDING_AUTH_TTL: u64 = 600;
+
+/// Everything we need to remember between redirecting to the provider and its callback.
+#[derive(Serialize, Deserialize)]
+pub struct PendingAuth {
+    pub provider: String,
+    pub csrf_state: String,
+    pub pkce_verifier: String,
+    pub nonce: String,
+    /// Whether the user asked for a long-lived session on the login page.
+    #[serde(default)]
+    pub remember_me: bool,
+}
+
+fn pending_auth_key(pre_auth_id: &str) -> String {
+    format!("pre_auth:{pre_auth_id}")
+}
+
+pub async fn store_pending_auth(
+    redis: &RedisPool,
+    pre_auth_id: &str,
+    pending: &PendingAuth,
+) -> Result<(), Box<dyn Error>> {
+    let mut con = redis.connection().await?;
+    let value = serde_json::to_string(pending)?;
+    con.set_ex::<_, _, ()>(pending_auth_key(pre_auth_id), value, PENDING_AUTH_TTL).await?;
+    Ok(())
+}
+
+/// Reads and deletes the pending login in one step, so a state can never be used twice.
+pub async fn take_pending_auth(redis: &RedisPool, pre_auth_id: &str) -> Result<Option<PendingAuth>, Box<dyn Error>> {
+    let mut con = redis.connection().await?;
+    let value: Option<String> = con.get_del(pending_auth_key(pre_auth_id)).await?;
+    match value {
+        Some(value) => Ok(Some(serde_json::from_str(&value)?)),
+        None => Ok(None),
+    }
+}
@@ -1 +1,2 @@
-pub mod session_setting;
\ No newline at end of file
+pub mod pool;
+pub mod auth_state;
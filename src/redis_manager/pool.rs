@@ -0,0 +1,74 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use redis::{
+    aio::{ConnectionManager, ConnectionManagerConfig},
+    Client, ErrorKind, RedisError, RedisResult,
+};
+use tokio::sync::OnceCell;
+
+// after a failed connect, requests skip redis for this long instead of waiting on it again
+const RECONNECT_COOLDOWN: Duration = Duration::from_secs(10);
+
+/// The one redis connection of the app, shared by every request.
+///
+/// The `ConnectionManager` multiplexes all commands over a single connection and reconnects
+/// with exponential back-off when it drops. It is only established on first use, so the app
+/// also starts while redis is down; callers get an error and fall back to Postgres.
+#[derive(Clone)]
+pub struct RedisPool {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    client: Client,
+    manager: OnceCell<ConnectionManager>,
+    last_failure: Mutex<Option<Instant>>,
+}
+
+impl RedisPool {
+    pub fn new(redis_url: &str) -> Self {
+        RedisPool {
+            inner: Arc::new(Inner {
+                client: Client::open(redis_url).expect("Invalid REDIS_URL"),
+                manager: OnceCell::new(),
+                last_failure: Mutex::new(None),
+            }),
+        }
+    }
+
+    /// A handle on the shared connection, cheap to clone and use for a single request.
+    pub async fn connection(&self) -> RedisResult<ConnectionManager> {
+        if let Some(manager) = self.inner.manager.get() {
+            return Ok(manager.clone());
+        }
+
+        if let Some(last_failure) = *self.inner.last_failure.lock().expect("redis lock poisoned") {
+            if last_failure.elapsed() < RECONNECT_COOLDOWN {
+                return Err(RedisError::from((ErrorKind::IoError, "redis is unavailable")));
+            }
+        }
+
+        let config = ConnectionManagerConfig::new()
+            .set_number_of_retries(3)
+            .set_max_delay(2000)
+            .set_connection_timeout(Duration::from_secs(1))
+            .set_response_timeout(Duration::from_millis(500));
+
+        let manager = self
+            .inner
+            .manager
+            .get_or_try_init(|| ConnectionManager::new_with_config(self.inner.client.clone(), config))
+            .await;
+
+        match manager {
+            Ok(manager) => Ok(manager.clone()),
+            Err(err) => {
+                *self.inner.last_failure.lock().expect("redis lock poisoned") = Some(Instant::now());
+                Err(err)
+            }
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    middleware::from_fn_with_state,
+    response::IntoResponse,
+    routing::{delete, get},
+    Json, Router,
+};
+use chrono::{Duration, Utc};
+use entity::api_token;
+use migration::sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder, Set,
+};
+use serde_json::json;
+use uuid::Uuid;
+
+use super::extractors::AuthUser;
+use super::middlewares::require_csrf;
+use crate::api_tokens::{generate_token, join_scopes, split_scopes};
+use crate::error::{AppError, AppResult, FieldError};
+use crate::models::user_models::{ApiTokenModel, CreateApiTokenModel};
+use crate::state::AppState;
+
+const MAX_NAME_LENGTH: usize = 100;
+const MAX_EXPIRES_IN_DAYS: i64 = 3650;
+
+/// Token management is only reachable with the session cookie, a token can't mint new tokens.
+pub fn api_token_routes(state: &AppState) -> Router<AppState> {
+    Router::new()
+        .route("/me/tokens", get(list_tokens).post(create_token))
+        .route("/me/tokens/:id", delete(revoke_token))
+        .route_layer(from_fn_with_state(state.clone(), require_csrf))
+}
+
+fn token_model(token: api_token::Model) -> ApiTokenModel {
+    ApiTokenModel {
+        id: token.id,
+        name: token.name,
+        scopes: split_scopes(&token.scopes),
+        last_used_at: token.last_used_at,
+        expires_at: token.expires_at,
+        created_at: token.created_at,
+    }
+}
+
+/// Creates a token, its clear text is part of this response only.
+async fn create_token(
+    AuthUser(user): AuthUser,
+    State(db): State<Arc<DatabaseConnection>>,
+    Json(create): Json<CreateApiTokenModel>,
+) -> AppResult<impl IntoResponse> {
+    let mut errors = Vec::new();
+    let name = create.name.trim().to_string();
+    if name.is_empty() || name.chars().count() > MAX_NAME_LENGTH {
+        errors.push(FieldError::new(
+            "name",
+            format!("Name must have between 1 and {} characters", MAX_NAME_LENGTH),
+        ));
+    }
+    if create.scopes.is_empty() {
+        errors.push(FieldError::new("scopes", "At least one scope is required"));
+    }
+    if create.expires_in_days.is_some_and(|days| !(1..=MAX_EXPIRES_IN_DAYS).contains(&days)) {
+        errors.push(FieldError::new(
+            "expires_in_days",
+            format!("expires_in_days must be between 1 and {}", MAX_EXPIRES_IN_DAYS),
+        ));
+    }
+    if !errors.is_empty() {
+        return Err(AppError::Validation(errors));
+    }
+
+    let expires_at = create
+        .expires_in_days
+        .map(|days| (Utc::now() + Duration::days(days)).into());
+
+    let (token, token_hash) = generate_token();
+
+    let created = api_token::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        user_id: Set(user.uuid),
+        name: Set(name),
+        token_hash: Set(token_hash),
+        scopes: Set(join_scopes(&create.scopes)),
+        last_used_at: Set(None),
+        expires_at: Set(expires_at),
+        ..Default::default()
+    }
+    .insert(db.as_ref())
+    .await?;
+
+    tracing::info!("User {} created the API token {}", user.uuid, created.id);
+    let mut body = json!(token_model(created));
+    body["token"] = json!(token);
+    Ok((StatusCode::CREATED, Json(body)))
+}
+
+async fn list_tokens(
+    AuthUser(user): AuthUser,
+    State(db): State<Arc<DatabaseConnection>>,
+) -> AppResult<impl IntoResponse> {
+    let tokens = api_token::Entity::find()
+        .filter(api_token::Column::UserId.eq(user.uuid))
+        .order_by_desc(api_token::Column::CreatedAt)
+        .all(db.as_ref())
+        .await?;
+
+    let tokens: Vec<ApiTokenModel> = tokens.into_iter().map(token_model).collect();
+    Ok((StatusCode::OK, Json(tokens)))
+}
+
+async fn revoke_token(
+    AuthUser(user): AuthUser,
+    State(db): State<Arc<DatabaseConnection>>,
+    Path(id): Path<Uuid>,
+) -> AppResult<impl IntoResponse> {
+    let result = api_token::Entity::delete_many()
+        .filter(api_token::Column::Id.eq(id))
+        .filter(api_token::Column::UserId.eq(user.uuid))
+        .exec(db.as_ref())
+        .await?;
+
+    if result.rows_affected == 0 {
+        return Err(AppError::NotFound("Token not found".to_string()));
+    }
+
+    tracing::info!("User {} revoked the API token {}", user.uuid, id);
+    Ok(StatusCode::NO_CONTENT)
+}
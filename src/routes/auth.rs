@@ -1,136 +1,109 @@
-use crate::redis_manager::session_setting::{get_session_id, set_session_id};
+use crate::config::Config;
+use crate::error::AppResult;
+use crate::redis_manager::pool::RedisPool;
+use crate::session_store::{ClientInfo, SessionError, SessionLifetime, SessionRecord, SessionStore};
+use crate::state::AppState;
 use axum::body::Body;
-use axum::extract::Query;
-use axum::http::HeaderMap;
-use axum::response::{Html, Redirect};
-use axum::routing::get;
-use axum::Extension;
+use axum::extract::{Path, Query, State};
+use axum::middleware::from_fn_with_state;
+use axum::response::{Html, Redirect, Response};
+use axum::routing::{get, post};
 use axum::{response::IntoResponse, Router};
 
 use axum_extra::headers;
 use axum_extra::TypedHeader;
 
-use chrono::{Duration, Utc};
+use chrono::Utc;
 use migration::sea_orm::ColumnTrait;
-use migration::sea_orm::{DatabaseConnection, EntityTrait, QueryFilter, Set};
-use oauth2::{
-    basic::BasicClient, AuthUrl, AuthorizationCode, ClientId, ClientSecret, CsrfToken, RedirectUrl,
-    Scope, TokenResponse, TokenUrl,
+use entity::{
+    api_token, auth_token, password_credential, recovery_code, sea_orm_active_enums::Role,
+    totp_credential, user, user_identity,
+};
+use http::StatusCode;
+use migration::sea_orm::{
+    ActiveModelTrait, ConnectionTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, Set,
+    TransactionTrait,
 };
-use reqwest::header;
 
 use std::collections::HashMap;
-use std::env;
 use std::sync::Arc;
-use tokio::sync::Mutex;
 use tower_cookies::cookie::SameSite;
 use tower_cookies::{Cookie, Cookies};
 use uuid::Uuid;
 
-use super::middlewares::user_expired;
-
-pub fn auth_user_routes(db: Arc<DatabaseConnection>) -> Router {
-    let oauth_client = create_oauth_client();
+use super::mfa::has_second_factor;
+use super::extractors::AuthUser;
+use super::middlewares::{clear_session_cookies, require_auth, require_csrf, user_expired};
+use super::oauth_state::{auth_error_page, begin_auth, verify_auth_state};
+use super::providers::{ProviderRegistry, VerifiedIdentity};
+use super::sessions::current_session;
+use super::token_auth::revoke_user_refresh_tokens;
 
+pub fn auth_user_routes(state: &AppState) -> Router<AppState> {
     Router::new()
-        .route("/auth", get(auth))
-        .route("/redirect", get(redirect_auth))
-        .route("/dashboard", get(dashboard))
+        .route("/auth/:provider", get(auth))
+        .route("/auth/:provider/callback", get(redirect_auth))
+        .route("/dashboard", get(dashboard).route_layer(from_fn_with_state(state.clone(), require_auth)))
         .route("/login", get(login))
-        .route("/logout", get(logout))
-        .layer(axum::middleware::from_fn(user_expired))
-        .layer(Extension(oauth_client))
-        .layer(Extension(db))
+        .route("/logout", post(logout).route_layer(from_fn_with_state(state.clone(), require_csrf)))
+        .layer(from_fn_with_state(state.clone(), user_expired))
 
 }
 
-async fn login() -> impl IntoResponse {
-    Html(
-       r#"
-           <form action="http://localhost:3010/auth_sign_on">
-               <input type="submit" value="Sign Up With Google" />
-           </form>
-
-           <form action="http://localhost:3010/auth">
-               <input type="submit" value="Login With Google" />
+async fn login(State(providers): State<Arc<ProviderRegistry>>) -> impl IntoResponse {
+    let forms: String = providers
+        .iter()
+        .map(|provider| {
+            format!(
+                r#"
+           <form action="/auth/{}">
+               <label><input type="checkbox" name="remember_me" value="true" /> Remember me</label>
+               <input type="submit" value="Continue With {}" />
            </form>
        "#,
-    )
+                provider.name, provider.display_name
+            )
+        })
+        .collect();
+
+    Html(forms)
 }
 
 
 async fn dashboard(
+    AuthUser(user): AuthUser,
+    State(sessions): State<Arc<dyn SessionStore>>,
     cookie_header: Option<TypedHeader<headers::Cookie>>,
-    Extension(db): Extension<Arc<DatabaseConnection>>,
-) -> impl IntoResponse {
-    let mut name = "".to_string();
-    let mut email = "".to_string();
-
-    //Take the existing session from the cookie
-    if let Some(TypedHeader(ref cookie)) = cookie_header {
-        //Find the session_id in session table
-        if let Some(session_id) = cookie.get("session_id") {
-            // Attempt to convert the session_id to a UUID
-
-            if get_session_id(session_id.to_string()).await.unwrap_or(false) {
-                println!("Session exists");
-            } else {
-                println!("Session does not exist");
-                return Redirect::temporary("/login").into_response();
-            }
-
-            let session_uuid = match Uuid::parse_str(session_id) {
-                Ok(uuid) => uuid,
-                Err(err) => {
-                    println!("Failed to parse session_id as UUID: {}", err);
-                    return Html("Invalid session ID format.".to_string()).into_response();
-                }
-            };
-
-            // Query the database for the session
-            //Get the user_id from the session table
-            let session_query = entity::session::Entity::find()
-                .filter(entity::session::Column::SessionId.eq(session_uuid))
-                .one(db.as_ref())
-                .await;
-
-            if let Ok(Some(session)) = session_query {
-                println!("Session found in database: {:?}", session);
-
-                //Select the user
-                //Compare the existing user_id from the session to id in user table
-                let user_query = entity::user::Entity::find()
-                    .filter(entity::user::Column::Uuid.eq(session.user_id))
-                    .one(db.as_ref())
-                    .await
-                    .unwrap()
-                    .unwrap();
-
-                name = user_query.name;
-                email = user_query.email;
-            }
-        }
-    }
+) -> AppResult<impl IntoResponse> {
+    let csrf_token = current_session(sessions.as_ref(), cookie_header, &user)
+        .await?
+        .map(|session| session.csrf_token)
+        .unwrap_or_default();
 
-    Html(
+    Ok(Html(
         format!(
             r#"
             <h1>User info : <br> name - {}, <br> email - {}</h1>
-            <form action="http://localhost:3010/logout">
+            <form action="/logout" method="post">
+                <input type="hidden" name="csrf_token" value="{}" />
                 <input type="submit" value="Logout" />
             </form>
         "#,
-            name, email
+            user.name, user.email, csrf_token
         )
         .to_string(),
     )
-    .into_response()
+    .into_response())
 }
 
 async fn auth(
-    Extension(oauth_client): Extension<Arc<Mutex<BasicClient>>>,
-    Extension(db): Extension<Arc<DatabaseConnection>>,
+    Path(provider_name): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
+    State(providers): State<Arc<ProviderRegistry>>,
+    State(sessions): State<Arc<dyn SessionStore>>,
+    State(redis): State<RedisPool>,
     cookie_header: Option<TypedHeader<headers::Cookie>>,
+    cookies: Cookies,
 ) -> impl IntoResponse {
     // Check for an existing session
     if let Some(TypedHeader(ref cookie)) = cookie_header {
@@ -139,239 +112,303 @@ async fn auth(
             let session_uuid = match Uuid::parse_str(session_id) {
                 Ok(uuid) => uuid,
                 Err(err) => {
-                    println!("Failed to parse session_id as UUID: {}", err);
+                    tracing::warn!("Failed to parse session_id as UUID: {}", err);
                     return Html("Invalid session ID format.".to_string()).into_response();
                 }
             };
 
-            // Query the database for the session
-            let query = entity::session::Entity::find()
-                .filter(entity::session::Column::SessionId.eq(session_uuid))
-                .one(db.as_ref())
-                .await;
-
-            if let Ok(Some(_session)) = query {
-                println!("Session found in database: {:?}", _session);
+            if let Ok(Some(_)) = sessions.get(session_uuid).await {
                 // Session is valid, proceed without re-authenticating
                 return Redirect::temporary("/dashboard").into_response();
             }
         }
     }
 
-    // Generate the OAuth authorization URL and CSRF token
-    let (auth_url, _csrf_token) = oauth_client
-        .lock()
-        .await
-        .authorize_url(CsrfToken::new_random)
-        .add_scope(Scope::new(
-            "https://www.googleapis.com/auth/userinfo.email".to_string(),
-        ))
-        .add_scope(Scope::new(
-            "https://www.googleapis.com/auth/userinfo.profile".to_string(),
-        ))
-        .url();
+    let Some(provider) = providers.get(&provider_name) else {
+        return auth_error_page(StatusCode::NOT_FOUND, "Unknown login provider.");
+    };
+
+    // Generate the authorization URL together with its CSRF token, nonce and PKCE challenge
+    let (auth_url, mut pending_auth) = provider.authorize_url();
+    pending_auth.remember_me = params.get("remember_me").is_some_and(|value| value == "true");
+
+    if let Err(response) = begin_auth(&redis, &cookies, pending_auth).await {
+        return response;
+    }
 
     // If session is not found or is invalid, redirect to the OAuth authorization URL
-    Redirect::temporary(&auth_url.to_string()).into_response()
+    Redirect::temporary(auth_url.as_ref()).into_response()
 }
 
+// every extractor is a separate argument of the handler
+#[allow(clippy::too_many_arguments)]
 async fn redirect_auth(
+    Path(provider_name): Path<String>,
     Query(params): Query<HashMap<String, String>>,
-    Extension(oauth_client): Extension<Arc<Mutex<BasicClient>>>,
-    Extension(db): Extension<Arc<DatabaseConnection>>,
+    State(providers): State<Arc<ProviderRegistry>>,
+    State(db): State<Arc<DatabaseConnection>>,
+    State(sessions): State<Arc<dyn SessionStore>>,
+    State(config): State<Arc<Config>>,
+    State(redis): State<RedisPool>,
+    client: ClientInfo,
     cookies: Cookies,
 ) -> impl IntoResponse {
 
     let state_param = params.get("state").map(|s| s.to_string());
 
-    if let Some(ref state_param) = state_param {
-        let csrf_token = CsrfToken::new(state_param.clone());
-        println!("Received CSRF token: {}", csrf_token.clone().secret());
-    } else {
-        return Html("Missing CSRF token".to_string()).into_response();
+    let Some(provider) = providers.get(&provider_name) else {
+        return auth_error_page(StatusCode::NOT_FOUND, "Unknown login provider.");
+    };
+
+    // the state has to match the one we handed out to this very browser
+    let pending_auth = match verify_auth_state(&redis, &cookies, &provider.name, state_param.as_ref()).await {
+        Ok(pending_auth) => pending_auth,
+        Err(response) => return response,
+    };
+
+    let Some(code) = params.get("code") else {
+        tracing::warn!("Missing code parameter");
+        return Html("Missing code".to_string()).into_response();
+    };
+
+    let remember_me = pending_auth.remember_me;
+    let identity = match provider.exchange_and_verify(code, pending_auth).await {
+        Ok(identity) => identity,
+        Err(response) => return response,
+    };
+
+    // first login registers the user, an existing account with the same verified email is linked
+    let user = match find_or_create_user(db.as_ref(), sessions.as_ref(), &provider.name, identity).await {
+        Ok(user) => user,
+        Err(err) => {
+            tracing::error!("Failed to resolve the user for the login: {:?}", err);
+            return auth_error_page(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Could not sign you in, please try again later.",
+            );
+        }
+    };
+
+    let mfa_pending = match has_second_factor(db.as_ref(), user.uuid).await {
+        Ok(mfa_pending) => mfa_pending,
+        Err(err) => {
+            tracing::error!("Failed to look up the second factor: {:?}", err);
+            return auth_error_page(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Could not sign you in, please try again later.",
+            );
+        }
+    };
+
+    let session = config.session.lifetime.new_session(user.uuid, mfa_pending, remember_me, client);
+    if let Err(err) = start_session(sessions.as_ref(), &cookies, session).await {
+        tracing::error!("Failed to insert session: {:?}", err);
+        return auth_error_page(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Could not sign you in, please try again later.",
+        );
     }
 
-    if let Some(code) = params.get("code") {
-        let token_result = oauth_client
-            .lock()
-            .await
-            .exchange_code(AuthorizationCode::new(code.to_string()))
-            .request_async(oauth2::reqwest::async_http_client)
-            .await;
-
-        match token_result {
-            Ok(token) => {
-                let access_token = token.access_token().secret();
-
-                let url = "https://www.googleapis.com/oauth2/v2/userinfo?oauth_token=".to_owned()
-                    + access_token;
-
-                println!("Access Token: {:?}", access_token);
-
-                let body_text = reqwest::get(url)
-                    .await
-                    .map_err(|_| "OAuth: reqwest failed to query userinfo")
-                    .expect("Something bad happened")
-                    .text()
-                    .await
-                    .unwrap();
-
-                println!("Userinfo response: {}", body_text);
-
-                let body: serde_json::Value = serde_json::from_str(&body_text)
-                    .map_err(|_| "OAuth: Serde failed to parse userinfo")
-                    .expect("Failed to parse userinfo response");
-
-                let email = body["email"]
-                    .as_str()
-                    .ok_or("Oauth: Failed to parse email form address")
-                    .map(|s| s.to_owned());
-
-                if let Ok(email) = email {
-                    let verified_email = body["verified_email"]
-                        .as_bool()
-                        .ok_or("Oauth: Serde failed to parse verified email")
-                        .unwrap();
-
-                    if !verified_email {
-                        return Html("Email is not verified.".to_string()).into_response();
-                    }
-
-                    let query = entity::user::Entity::find().filter(entity::user::Column::Email.eq(email.clone()))
-                    .one(db.as_ref())
-                    .await;
-
-                    match query {
-                        Ok(Some(user)) => {
-                            //add all necessary fields into the sessions table.
-
-                            let session_id = Uuid::new_v4();
-                            let expires_at_val = Utc::now() + Duration::hours(1);
-
-                            let new_session = entity::session::ActiveModel {
-                                session_id: Set(session_id.clone()),
-                                user_id: Set(user.uuid),
-                                expires_at: Set(expires_at_val.into()),
-                                csfr_token: Set(state_param.unwrap_or_else(|| "".to_string())), // Store the CSRF token in the session
-                                ..Default::default()
-                            };
-
-                            match set_session_id(session_id.to_string()).await {
-                                Ok(_) => println!("The session_id was stored in redis"), 
-                                Err(_) => eprintln!("The error occured in storing session_id into redis"),
-                            }
-
-                            entity::session::Entity::insert(new_session)
-                                .exec(db.as_ref())
-                                .await
-                                .expect("Failed to insert session");
-
-                            //add session_id to Cookies
-
-                            let mut cookie = Cookie::new("session_id", session_id.to_string());
-                            cookie.set_http_only(true);
-                            cookie.set_path("/");
-                            cookie.set_secure(true);
-                            cookie.set_same_site(SameSite::Strict);
-                            cookies.add(cookie);
-
-                            let body = Body::from(
-                                         r#"
-                                             <html>
-                                             <head>
-                                                 <meta http-equiv="refresh" content="0; url=/dashboard" />
-                                             </head>
-                                             <body>
-                                                 User authenticated. Redirecting...
-                                             </body>
-                                             </html>
-                                         "#,
-                                     ).into_response();
-                            body
-                        }
-                        Ok(None) => Html("User not found".to_string()).into_response(),
-                        Err(err) => {
-                            Html(format!("Database query failed: {:?}", err)).into_response()
-                        }
-                    }
-                } else {
-                    Html("Failed to parse email from userinfo response".to_string()).into_response()
-                }
-            }
-            Err(err) => {
-                eprintln!("Failed to exchange code: {:?}", err);
-                Html("Failed to exchange code".to_string()).into_response()
+    let next = if mfa_pending { "/auth/mfa" } else { "/dashboard" };
+
+    Body::from(format!(
+        r#"
+            <html>
+            <head>
+                <meta http-equiv="refresh" content="0; url={}" />
+            </head>
+            <body>
+                User authenticated. Redirecting...
+            </body>
+            </html>
+        "#,
+        next
+    )).into_response()
+}
+
+/// Resolves the user behind a verified identity.
+///
+/// Users are matched by the provider's stable subject first. Without a match an existing
+/// account with the same email gets linked to the identity, otherwise a new user is
+/// registered. Provider emails are verified, so linking also verifies the account; an
+/// account that was not verified before loses its sessions and credentials on the way.
+async fn find_or_create_user(
+    db: &DatabaseConnection,
+    sessions: &dyn SessionStore,
+    provider: &str,
+    identity: VerifiedIdentity,
+) -> AppResult<user::Model> {
+    if let Some((_, Some(user))) = user_identity::Entity::find()
+        .filter(user_identity::Column::Provider.eq(provider))
+        .filter(user_identity::Column::Subject.eq(identity.subject.clone()))
+        .find_also_related(user::Entity)
+        .one(db)
+        .await?
+    {
+        return Ok(user);
+    }
+
+    let txn = db.begin().await?;
+
+    let user = match user::Entity::find()
+        .filter(user::Column::Email.eq(identity.email.clone()))
+        .one(&txn)
+        .await?
+    {
+        Some(user) if user.email_verified_at.is_some() => {
+            tracing::info!("Linking user {} to {} subject {}", user.uuid, provider, identity.subject);
+            user
+        }
+        Some(user) => {
+            // Whoever registered the unverified account never proved owning the address, so
+            // nothing they set up may keep working next to the provider login.
+            tracing::info!(
+                "Linking unverified user {} to {} subject {}, dropping its credentials",
+                user.uuid, provider, identity.subject
+            );
+            drop_credentials(&txn, user.uuid).await?;
+            // before the commit, a failure leaves the account unlinked instead of shared
+            sessions.delete_for_user(user.uuid).await?;
+
+            let mut user: user::ActiveModel = user.into();
+            user.email_verified_at = Set(Some(Utc::now().into()));
+            user.update(&txn).await?
+        }
+        None => {
+            user::ActiveModel {
+                name: Set(identity.name),
+                email: Set(identity.email.clone()),
+                uuid: Set(Uuid::new_v4()),
+                role: Set(Role::Author),
+                email_verified_at: Set(Some(Utc::now().into())),
+                ..Default::default()
             }
+            .insert(&txn)
+            .await?
         }
-    } else {
-        println!("Missing code parameter");
-        Html("Missing code".to_string()).into_response()
+    };
+
+    user_identity::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        user_id: Set(user.uuid),
+        provider: Set(provider.to_string()),
+        subject: Set(identity.subject),
+        email: Set(identity.email),
+        ..Default::default()
     }
+    .insert(&txn)
+    .await?;
+
+    txn.commit().await?;
+
+    Ok(user)
 }
 
-fn create_oauth_client() -> Arc<Mutex<BasicClient>> {
-    let client_id = ClientId::new(env::var("GOOGLE_OAUTH_CLIENT_ID").expect("Missing CLIENT_ID"));
-    let client_secret =
-        ClientSecret::new(env::var("GOOGLE_OAUTH_CLIENT_SECRET").expect("Missing CLIENT_SECRET"));
-    let auth_url = AuthUrl::new(env::var("OAUTH_AUTH_URL").expect("Missing AUTH_URL"))
-        .expect("Invalid AUTH_URL");
-    let token_url = TokenUrl::new(env::var("OAUTH_TOKEN_URL").expect("Missing TOKEN_URL"))
-        .expect("Invalid TOKEN_URL");
-    let redirect_url =
-        RedirectUrl::new(env::var("OAUTH_REDIRECT_URL").expect("Missing REDIRECT_URL"))
-            .expect("Invalid REDIRECT_URL");
-    let oauth_client = BasicClient::new(client_id, Some(client_secret), auth_url, Some(token_url))
-        .set_redirect_uri(redirect_url);
-
-    Arc::new(Mutex::new(oauth_client))
+/// Removes every way into the account but the provider login being linked: the password,
+/// API and refresh tokens, the second factor and pending email tokens.
+async fn drop_credentials<C: ConnectionTrait>(db: &C, user_id: Uuid) -> Result<(), DbErr> {
+    password_credential::Entity::delete_by_id(user_id).exec(db).await?;
+    api_token::Entity::delete_many()
+        .filter(api_token::Column::UserId.eq(user_id))
+        .exec(db)
+        .await?;
+    revoke_user_refresh_tokens(db, user_id).await?;
+    totp_credential::Entity::delete_many()
+        .filter(totp_credential::Column::UserId.eq(user_id))
+        .exec(db)
+        .await?;
+    recovery_code::Entity::delete_many()
+        .filter(recovery_code::Column::UserId.eq(user_id))
+        .exec(db)
+        .await?;
+    auth_token::Entity::delete_many()
+        .filter(auth_token::Column::UserId.eq(user_id))
+        .exec(db)
+        .await?;
+    Ok(())
 }
 
-async fn logout(
-    Extension(db): Extension<Arc<DatabaseConnection>>,
-    cookie_header: Option<TypedHeader<headers::Cookie>>,
-) -> impl IntoResponse {
-    // Check for an existing session
-    if let Some(TypedHeader(ref cookie)) = cookie_header {
-        if let Some(session_id) = cookie.get("session_id") {
-            // Attempt to convert the session_id to a UUID
-            let session_uuid = match Uuid::parse_str(session_id) {
-                Ok(uuid) => uuid,
-                Err(err) => {
-                    println!("Failed to parse session_id as UUID: {}", err);
-                    return Html("Invalid session ID format.".to_string()).into_response();
-                }
-            };
-            // Delete the session from the database
-            let result = entity::session::Entity::delete_by_id(session_uuid)
-                .exec(db.as_ref())
-                .await;
-
-            match result {
-                Ok(delete_result) => {
-                    if delete_result.rows_affected > 0 {
-                        println!("Session successfully deleted from the database.");
-                    } else {
-                        println!("No session found with the given session_id.");
-                    }
-                }
-                Err(err) => {
-                    println!("Error deleting session from database: {:?}", err);
-                    return Html("Failed to delete session.".to_string()).into_response();
-                }
-            }
+/// Stores a new session and hands the `session_id` cookie to the browser.
+///
+/// Sessions come from `SessionLifetime::new_session`. With `mfa_pending` the session only
+/// counts as logged in after the second factor was completed on `/auth/mfa`.
+pub async fn start_session(
+    sessions: &dyn SessionStore,
+    cookies: &Cookies,
+    session: SessionRecord,
+) -> Result<(), SessionError> {
+    sessions.create(&session).await?;
+
+    //add session_id and the CSRF token to Cookies
+    add_session_cookies(cookies, &session);
+
+    Ok(())
+}
 
-            // Clear the session cookie by setting it with an expiration in the past
-            let mut headers = HeaderMap::new();
-            headers.insert(
-                header::SET_COOKIE,
-                "session_id=deleted; HttpOnly; Secure; SameSite=Strict; Max-Age=0"
-                    .parse()
-                    .unwrap(),
-            );
+/// The `session_id` cookie and the `csrf_token` cookie the frontend echoes back in the
+/// `X-CSRF-Token` header. Both expire together with the session, only the CSRF token is
+/// readable from JavaScript.
+fn add_session_cookies(cookies: &Cookies, session: &SessionRecord) {
+    let max_age = (session.expires_at - Utc::now()).num_seconds().max(0);
+
+    for (name, value, http_only) in [
+        ("session_id", session.session_id.to_string(), true),
+        ("csrf_token", session.csrf_token.clone(), false),
+    ] {
+        let mut cookie = Cookie::new(name, value);
+        cookie.set_http_only(http_only);
+        cookie.set_path("/");
+        cookie.set_secure(true);
+        cookie.set_same_site(SameSite::Strict);
+        cookie.set_max_age(tower_cookies::cookie::time::Duration::seconds(max_age));
+        cookies.add(cookie);
+    }
+}
 
-            return (headers, Redirect::temporary("/login")).into_response();
+/// Slides the expiry of a session that is in use and refreshes the cookie with it.
+///
+/// A failed renewal is only logged, the session stays valid until its current expiry.
+/// Returns `None` when the session was ended while the request ran.
+pub async fn renew_session(
+    sessions: &dyn SessionStore,
+    lifetime: &SessionLifetime,
+    cookies: &Cookies,
+    session: SessionRecord,
+) -> Option<SessionRecord> {
+    let Some(renewed) = lifetime.renewed(&session) else {
+        return Some(session);
+    };
+
+    match sessions.update(&renewed).await {
+        Ok(true) => {
+            add_session_cookies(cookies, &renewed);
+            Some(renewed)
         }
+        Ok(false) => None,
+        Err(err) => {
+            tracing::error!("Failed to renew the session {}: {}", session.session_id, err);
+            Some(session)
+        }
+    }
+}
+
+/// Ends the session of the cookie, a POST behind `require_csrf` so other sites can't sign
+/// the user out.
+async fn logout(
+    State(sessions): State<Arc<dyn SessionStore>>,
+    cookie_header: Option<TypedHeader<headers::Cookie>>,
+) -> AppResult<Response> {
+    let session_id = cookie_header
+        .as_ref()
+        .and_then(|TypedHeader(cookie)| cookie.get("session_id"))
+        .and_then(|session_id| session_id.parse::<Uuid>().ok());
+
+    // Delete the session from every store
+    if let Some(session_id) = session_id {
+        sessions.delete(session_id).await?;
     }
 
-    Redirect::temporary("/login").into_response()
+    let mut response = Redirect::to("/login").into_response();
+    clear_session_cookies(&mut response);
+    Ok(response)
 }
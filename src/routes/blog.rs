@@ -1,203 +1,288 @@
-use crate::models::blog_model::{CreateBlogModel, GetAllBlogsModel, GetBlogModel, UpdateBlogModel};
-use axum::extract::Path;
-use axum::routing::{delete, get};
+use crate::models::blog_model::{
+    BlogListQuery, CreateBlogModel, GetAllBlogsModel, GetBlogModel, PatchBlogModel, UpdateBlogModel,
+};
+use axum::extract::{Path, Query, State};
+use axum::middleware::from_fn_with_state;
+use axum::routing::{delete, get, patch};
 use axum::{
-    http::StatusCode,
+    http::{header, HeaderMap, StatusCode},
     response::IntoResponse,
     routing::{post, put},
     Extension, Json, Router,
 };
-use entity::{blog, user};
+use axum_extra::{headers::IfMatch, TypedHeader};
+use chrono::Utc;
+use entity::blog;
 use migration::sea_orm::ColumnTrait;
-use migration::sea_orm::{DatabaseConnection, EntityTrait, QueryFilter, Set};
+use migration::sea_orm::{
+    sea_query::{extension::postgres::PgExpr, Expr},
+    DatabaseConnection, EntityTrait, QueryFilter, QueryOrder, QuerySelect, Set,
+};
 use uuid::Uuid;
 use std::sync::Arc;
 
-pub fn blog_routes(db: Arc<DatabaseConnection>) -> Router</*AppState*/> {
+use super::etag::{check_if_match, etag, lost_update};
+use super::extractors::{AuthUser, ValidatedJson};
+use super::pagination::{next_link, page_size, Cursor};
+use super::middlewares::{require_auth, require_csrf};
+use crate::api_tokens::ApiScope;
+use crate::config::Config;
+use crate::error::{AppError, AppResult};
+use crate::policy::{authorize_blog, authorize_blog_create, Action};
+use crate::state::AppState;
+
+pub fn blog_routes(state: &AppState) -> Router<AppState> {
     Router::new()
         .route("/blog/insert", post(create_blog))
         .route("/blog/update/:id", put(update_blog))
+        .route("/blogs/:id", patch(patch_blog))
         .route("/blog/delete/:id", delete(delete_blog))
+        .route_layer(from_fn_with_state(state.clone(), require_csrf))
+        .route_layer(from_fn_with_state(state.clone(), require_auth))
         .route("/blog/:id", get(get_blog))
         .route("/blogs", get(get_all_blogs))
         .route("/blogs/user/:id", get(get_all_user_blogs))
-        .layer(Extension(db))
+        .layer(Extension(ApiScope::BlogWrite))
 }
 
 async fn get_all_user_blogs(
     Path(id): Path<Uuid>,
-    Extension(db): Extension<Arc<DatabaseConnection>>,
-) -> impl IntoResponse {
-    let blogs = entity::blog::Entity::find()
-        .filter(blog::Column::UserId.eq(id))
-        .all(db.as_ref())
-        .await;
-
-    match blogs {
-        Ok(res) => (
-            StatusCode::OK,
-            Json(GetAllBlogsModel {
-                blogs: res
-                    .iter()
-                    .map(|b| GetBlogModel {
-                        title: b.title.to_string(),
-                        content: b.content.to_string(),
-                        user_id: b.user_id,
-                        created_at: b.created_at,
-                        images: Some(b.images.clone()).unwrap()
-                    })
-                    .collect(),
-            }),
-        ),
-        Err(_) => (StatusCode::NOT_FOUND, Json::default()),
-    }
+    State(db): State<Arc<DatabaseConnection>>,
+    State(config): State<Arc<Config>>,
+    Query(query): Query<BlogListQuery>,
+) -> AppResult<impl IntoResponse> {
+    list_blogs(db.as_ref(), &config, &format!("/blogs/user/{}", id), Some(id), query).await
 }
 
-async fn get_all_blogs(Extension(db): Extension<Arc<DatabaseConnection>>) -> impl IntoResponse {
-    //extract all blogs from db
-    let blogs = entity::blog::Entity::find().all(db.as_ref()).await;
-
-    //match the vector of blogs
-    match blogs {
-        Ok(res) => (
-            StatusCode::OK,
-            //if the result is Ok return Json with the Vector of users
-            Json(GetAllBlogsModel {
-                //iterate each blog
-                blogs: res
-                    .iter()
-                    //map each model of blog with its arguments
-                    .map(|b| GetBlogModel {
-                        title: (*b.title).to_string(),
-                        content: (*b.content).to_string(),
-                        user_id: b.user_id,
-                        created_at: b.created_at,
-                        images: Some(b.images.clone()).unwrap()
-                    })
-                    .collect(),
-            }),
-        ),
-        Err(_) => (StatusCode::NOT_FOUND, Json::default()),
+async fn get_all_blogs(
+    State(db): State<Arc<DatabaseConnection>>,
+    State(config): State<Arc<Config>>,
+    Query(query): Query<BlogListQuery>,
+) -> AppResult<impl IntoResponse> {
+    let author = query.author;
+    list_blogs(db.as_ref(), &config, "/blogs", author, query).await
+}
+
+/// One page of blogs, newest first unless `order=asc`. `path` is where the `Link` to the
+/// next page points.
+async fn list_blogs(
+    db: &DatabaseConnection,
+    config: &Config,
+    path: &str,
+    author: Option<Uuid>,
+    query: BlogListQuery,
+) -> AppResult<impl IntoResponse> {
+    let limit = page_size(query.limit);
+    let order = query.order.unwrap_or_default();
+
+    let mut select = blog::Entity::find()
+        .order_by(blog::Column::CreatedAt, order.order())
+        .order_by(blog::Column::Id, order.order())
+        .limit(limit + 1);
+
+    if let Some(author) = author {
+        select = select.filter(blog::Column::UserId.eq(author));
+    }
+    if let Some(from) = query.from {
+        select = select.filter(blog::Column::CreatedAt.gte(from));
+    }
+    if let Some(to) = query.to {
+        select = select.filter(blog::Column::CreatedAt.lt(to));
+    }
+    if let Some(tag) = &query.tag {
+        select = select.filter(PgExpr::contains(
+            Expr::col(blog::Column::Tags),
+            Expr::val(vec![tag.clone()]),
+        ));
     }
+    if let Some(cursor) = &query.cursor {
+        let cursor = Cursor::<i32>::decode(cursor)?;
+        select = select.filter(cursor.after(blog::Column::CreatedAt, blog::Column::Id, order));
+    }
+
+    // one row more than asked tells whether there is a next page
+    let mut blogs = select.all(db).await?;
+    let next_cursor = if blogs.len() as u64 > limit {
+        blogs.truncate(limit as usize);
+        blogs.last().map(|last| {
+            Cursor {
+                created_at: last.created_at,
+                id: last.id,
+            }
+            .encode()
+        })
+    } else {
+        None
+    };
+
+    let mut headers = HeaderMap::new();
+    if let Some(cursor) = &next_cursor {
+        let next = BlogListQuery {
+            cursor: Some(cursor.clone()),
+            ..query
+        };
+        if let Some(link) = next_link(&config.public_base_url, path, &next) {
+            headers.insert(header::LINK, link);
+        }
+    }
+
+    Ok((
+        StatusCode::OK,
+        headers,
+        Json(GetAllBlogsModel {
+            blogs: blogs.into_iter().map(GetBlogModel::from).collect(),
+            next_cursor,
+        }),
+    ))
+}
+
+async fn find_blog(db: &DatabaseConnection, id: i32) -> AppResult<blog::Model> {
+    entity::blog::Entity::find()
+        .filter(entity::blog::Column::Id.eq(id))
+        .one(db)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Blog not found".to_string()))
 }
 
 async fn get_blog(
     Path(id): Path<i32>,
-    Extension(db): Extension<Arc<DatabaseConnection>>,
-) -> impl IntoResponse {
-    let blog = entity::blog::Entity::find()
-        .filter(entity::blog::Column::Id.eq(id))
-        .one(db.as_ref())
-        .await
-        .unwrap()
-        .unwrap();
+    State(db): State<Arc<DatabaseConnection>>,
+) -> AppResult<impl IntoResponse> {
+    let blog = find_blog(db.as_ref(), id).await?;
 
-    (
+    Ok((
         StatusCode::OK,
-        Json(GetBlogModel {
-            title: blog.title,
-            content: blog.content,
-            user_id: blog.user_id,
-            created_at: blog.created_at,
-            images: Some(blog.images).unwrap(),
-        }),
-    )
+        TypedHeader(etag(blog.version)),
+        Json(GetBlogModel::from(blog)),
+    ))
+}
+
+/// Writes `changes` and bumps the version, unless somebody saved the blog since `current`
+/// was read.
+async fn save_blog(
+    db: &DatabaseConnection,
+    current: &blog::Model,
+    changes: blog::ActiveModel,
+) -> AppResult<blog::Model> {
+    let result = blog::Entity::update_many()
+        .set(changes)
+        .col_expr(blog::Column::Version, Expr::col(blog::Column::Version).add(1))
+        .col_expr(blog::Column::UpdatedAt, Expr::value(Utc::now()))
+        .filter(blog::Column::Id.eq(current.id))
+        .filter(blog::Column::Version.eq(current.version))
+        .exec(db)
+        .await?;
+
+    if result.rows_affected == 0 {
+        return Err(lost_update());
+    }
+    find_blog(db, current.id).await
 }
 
 //delete blog by its id
 async fn delete_blog(
+    AuthUser(user): AuthUser,
     Path(id): Path<i32>,
-    Extension(db): Extension<Arc<DatabaseConnection>>,
-) -> impl IntoResponse {
-    let blog = entity::blog::Entity::find()
-        .filter(entity::blog::Column::Id.eq(id))
-        .one(db.as_ref())
-        .await
-        .unwrap()
-        .unwrap();
+    State(db): State<Arc<DatabaseConnection>>,
+) -> AppResult<impl IntoResponse> {
+    let blog = find_blog(db.as_ref(), id).await?;
+
+    authorize_blog(&user, Action::DeleteBlog, &blog)?;
 
     entity::blog::Entity::delete_by_id(blog.id)
         .exec(db.as_ref())
-        .await
-        .unwrap();
+        .await?;
 
-    //db.close().await.unwrap();
-
-    (StatusCode::ACCEPTED, "Deleted")
+    Ok(StatusCode::NO_CONTENT)
 }
 
 async fn update_blog(
+    AuthUser(user): AuthUser,
     Path(id): Path<i32>,
-    Extension(db): Extension<Arc<DatabaseConnection>>,
-    Json(blog_data): Json<UpdateBlogModel>,
-) -> impl IntoResponse {
-
-    let mut blog: entity::blog::ActiveModel = entity::blog::Entity::find()
-        .filter(entity::blog::Column::Id.eq(id))
-        .one(db.as_ref())
-        .await
-        .unwrap()
-        .unwrap()
-        .into();
+    State(db): State<Arc<DatabaseConnection>>,
+    if_match: Option<TypedHeader<IfMatch>>,
+    ValidatedJson(blog_data): ValidatedJson<UpdateBlogModel>,
+) -> AppResult<impl IntoResponse> {
+    let blog = find_blog(db.as_ref(), id).await?;
 
-    blog.title = Set(blog_data.title);
-    blog.content = Set(blog_data.content);
+    authorize_blog(&user, Action::UpdateBlog, &blog)?;
+    check_if_match(if_match.as_deref(), blog.version)?;
 
-    blog::Entity::update(blog).exec(db.as_ref()).await.unwrap();
+    let changes = blog::ActiveModel {
+        title: Set(blog_data.title),
+        content: Set(blog_data.content),
+        ..Default::default()
+    };
+    let blog = save_blog(db.as_ref(), &blog, changes).await?;
 
-    (StatusCode::ACCEPTED, "Updated")
+    Ok((
+        StatusCode::OK,
+        TypedHeader(etag(blog.version)),
+        Json(GetBlogModel::from(blog)),
+    ))
 }
 
-async fn create_blog(
-    Extension(db): Extension<Arc<DatabaseConnection>>,
-    blog_data: Json<CreateBlogModel>,
-) -> impl IntoResponse {
-    // if the user's id (PRIMARY KEY) == user_id that is given as argument => insert the new blog
-    // Check if user exists
-    match user::Entity::find()
-        .filter(user::Column::Uuid.eq(blog_data.user_id))
-        .one(db.as_ref())
-        .await
-    {
-        Ok(Some(user)) => {
-            println!("User found: {:?}", user);
-
-            // User exists, insert blog
-            let blog_model = blog::ActiveModel {
-                title: Set(blog_data.title.to_owned()),
-                content: Set(blog_data.content.to_owned()),
-                user_id: Set(blog_data.user_id),
-                images: Set(Some(blog_data.images.clone()).unwrap()),
-                ..Default::default()
-            };
-
-            // Insertion to DB
-            match blog::Entity::insert(blog_model.clone())
-                .exec(db.as_ref())
-                .await
-            {
-                Ok(_) => (StatusCode::CREATED, format!("{:?}", blog_model)).into_response(),
-                Err(e) => {
-                    eprintln!("Database insertion error: {:?}", e);
-                    (
-                        StatusCode::INTERNAL_SERVER_ERROR,
-                        "Failed to insert blog".to_string(),
-                    )
-                        .into_response()
-                }
-            }
-        }
-        Ok(None) => {
-            // User not found
-            (StatusCode::FORBIDDEN, "You have no rights".to_string()).into_response()
-        }
-        Err(e) => {
-            eprintln!("Database query error: {:?}", e);
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to query user".to_string(),
-            )
-                .into_response()
-        }
+/// Changes only the fields present in the body. Send the `ETag` of the blog as `If-Match`
+/// to get a 412 instead of overwriting somebody else's edit.
+async fn patch_blog(
+    AuthUser(user): AuthUser,
+    Path(id): Path<i32>,
+    State(db): State<Arc<DatabaseConnection>>,
+    if_match: Option<TypedHeader<IfMatch>>,
+    ValidatedJson(patch): ValidatedJson<PatchBlogModel>,
+) -> AppResult<impl IntoResponse> {
+    let blog = find_blog(db.as_ref(), id).await?;
+
+    authorize_blog(&user, Action::UpdateBlog, &blog)?;
+    check_if_match(if_match.as_deref(), blog.version)?;
+
+    let mut changes = blog::ActiveModel::default();
+    if let Some(title) = patch.title {
+        changes.title = Set(title);
+    }
+    if let Some(content) = patch.content {
+        changes.content = Set(content);
     }
+    if let Some(images) = patch.images {
+        changes.images = Set(Some(images));
+    }
+    if let Some(tags) = patch.tags {
+        changes.tags = Set(tags);
+    }
+    let blog = save_blog(db.as_ref(), &blog, changes).await?;
+
+    Ok((
+        StatusCode::OK,
+        TypedHeader(etag(blog.version)),
+        Json(GetBlogModel::from(blog)),
+    ))
 }
+
+async fn create_blog(
+    AuthUser(author): AuthUser,
+    State(db): State<Arc<DatabaseConnection>>,
+    ValidatedJson(blog_data): ValidatedJson<CreateBlogModel>,
+) -> AppResult<impl IntoResponse> {
+    authorize_blog_create(&author)?;
+
+    // the author is always the owner of the session, never a client supplied id
+    let blog_model = blog::ActiveModel {
+        title: Set(blog_data.title),
+        content: Set(blog_data.content),
+        user_id: Set(author.uuid),
+        images: Set(blog_data.images),
+        tags: Set(blog_data.tags.unwrap_or_default()),
+        ..Default::default()
+    };
+
+    let blog = blog::Entity::insert(blog_model)
+        .exec_with_returning(db.as_ref())
+        .await?;
+
+    Ok((
+        StatusCode::CREATED,
+        [(header::LOCATION, format!("/blog/{}", blog.id))],
+        TypedHeader(etag(blog.version)),
+        Json(GetBlogModel::from(blog)),
+    ))
+}
\ No newline at end of file
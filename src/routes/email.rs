@@ -0,0 +1,213 @@
+use std::{collections::HashMap, sync::Arc};
+
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    middleware::from_fn_with_state,
+    response::{Html, IntoResponse, Response},
+    routing::{get, post},
+    Router,
+};
+use chrono::{Duration, Utc};
+use entity::{sea_orm_active_enums::AuthTokenKind, user};
+use migration::sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, Set,
+    SqlErr,
+};
+
+use super::extractors::{AuthUser, ValidatedJson};
+use super::middlewares::require_csrf;
+use crate::auth_tokens::{consume_token, issue_token};
+use crate::config::Config;
+use crate::error::{AppError, AppResult};
+use crate::mailer::{Email, Mailer};
+use crate::models::user_models::ChangeEmailModel;
+use crate::state::AppState;
+
+const VERIFICATION_TTL_HOURS: i64 = 24;
+
+pub fn email_routes(state: &AppState) -> Router<AppState> {
+    Router::new()
+        .route("/auth/email/verify", get(verify_email))
+        .route("/auth/email/verify/resend", post(resend_verification).route_layer(from_fn_with_state(state.clone(), require_csrf)))
+        .route("/auth/email/confirm_change", get(confirm_email_change))
+        .route("/me/email", post(change_email).route_layer(from_fn_with_state(state.clone(), require_csrf)))
+}
+
+/// Mails a verification link for the user's current address.
+pub async fn send_verification_email(
+    db: &DatabaseConnection,
+    config: &Config,
+    mailer: &dyn Mailer,
+    user: &user::Model,
+) -> Result<(), DbErr> {
+    let token = issue_token(
+        db,
+        config,
+        user.uuid,
+        AuthTokenKind::EmailVerification,
+        None,
+        Duration::hours(VERIFICATION_TTL_HOURS),
+    )
+    .await?;
+
+    let email = Email {
+        to: user.email.clone(),
+        subject: "Verify your email address".to_string(),
+        body: format!(
+            "Hi {},\n\nplease confirm your email address by opening this link:\n{}/auth/email/verify?token={}\n\nThe link expires in {} hours.",
+            user.name,
+            config.public_base_url,
+            token,
+            VERIFICATION_TTL_HOURS
+        ),
+    };
+
+    if let Err(err) = mailer.send(email).await {
+        tracing::error!("Failed to send the verification mail to user {}: {}", user.uuid, err);
+    }
+    Ok(())
+}
+
+fn result_page(status: StatusCode, message: &str) -> Response {
+    (
+        status,
+        Html(format!(
+            r#"
+            <h1>{}</h1>
+            <a href="/dashboard">Continue</a>
+        "#,
+            message
+        )),
+    )
+        .into_response()
+}
+
+async fn verify_email(
+    Query(params): Query<HashMap<String, String>>,
+    State(db): State<Arc<DatabaseConnection>>,
+    State(config): State<Arc<Config>>,
+) -> AppResult<Response> {
+    let Some(token) = params.get("token") else {
+        return Ok(result_page(StatusCode::BAD_REQUEST, "Missing token"));
+    };
+
+    let Some(token) = consume_token(db.as_ref(), &config, token, AuthTokenKind::EmailVerification).await? else {
+        return Ok(result_page(
+            StatusCode::BAD_REQUEST,
+            "This link is invalid, expired or was already used.",
+        ));
+    };
+
+    user::Entity::update_many()
+        .col_expr(
+            user::Column::EmailVerifiedAt,
+            migration::sea_orm::sea_query::Expr::value(Utc::now()),
+        )
+        .filter(user::Column::Uuid.eq(token.user_id))
+        .exec(db.as_ref())
+        .await?;
+
+    Ok(result_page(StatusCode::OK, "Your email address is verified."))
+}
+
+async fn resend_verification(
+    AuthUser(user): AuthUser,
+    State(db): State<Arc<DatabaseConnection>>,
+    State(config): State<Arc<Config>>,
+    State(mailer): State<Arc<dyn Mailer>>,
+) -> AppResult<impl IntoResponse> {
+    if user.email_verified_at.is_some() {
+        return Err(AppError::Conflict("Email is already verified".to_string()));
+    }
+
+    send_verification_email(db.as_ref(), &config, mailer.as_ref(), &user).await?;
+    Ok((StatusCode::ACCEPTED, "Sent"))
+}
+
+/// Starts an email change, the new address only replaces the old one after it was confirmed.
+async fn change_email(
+    AuthUser(user): AuthUser,
+    State(db): State<Arc<DatabaseConnection>>,
+    State(config): State<Arc<Config>>,
+    State(mailer): State<Arc<dyn Mailer>>,
+    ValidatedJson(change): ValidatedJson<ChangeEmailModel>,
+) -> AppResult<impl IntoResponse> {
+    let taken = user::Entity::find()
+        .filter(user::Column::Email.eq(change.email.clone()))
+        .one(db.as_ref())
+        .await?;
+
+    if taken.is_some() {
+        return Err(AppError::Conflict("Email is already registered".to_string()));
+    }
+
+    let token = issue_token(
+        db.as_ref(),
+        &config,
+        user.uuid,
+        AuthTokenKind::EmailChange,
+        Some(change.email.clone()),
+        Duration::hours(VERIFICATION_TTL_HOURS),
+    )
+    .await?;
+
+    let email = Email {
+        to: change.email,
+        subject: "Confirm your new email address".to_string(),
+        body: format!(
+            "Hi {},\n\nplease confirm your new email address by opening this link:\n{}/auth/email/confirm_change?token={}\n\nThe link expires in {} hours.",
+            user.name,
+            config.public_base_url,
+            token,
+            VERIFICATION_TTL_HOURS
+        ),
+    };
+
+    if let Err(err) = mailer.send(email).await {
+        tracing::error!("Failed to send the email change mail to user {}: {}", user.uuid, err);
+        return Err(AppError::BadGateway("Failed to send the confirmation mail".to_string()));
+    }
+
+    Ok((StatusCode::ACCEPTED, "Confirmation sent"))
+}
+
+async fn confirm_email_change(
+    Query(params): Query<HashMap<String, String>>,
+    State(db): State<Arc<DatabaseConnection>>,
+    State(config): State<Arc<Config>>,
+) -> AppResult<Response> {
+    let Some(token) = params.get("token") else {
+        return Ok(result_page(StatusCode::BAD_REQUEST, "Missing token"));
+    };
+
+    let Some(token) = consume_token(db.as_ref(), &config, token, AuthTokenKind::EmailChange).await? else {
+        return Ok(result_page(
+            StatusCode::BAD_REQUEST,
+            "This link is invalid, expired or was already used.",
+        ));
+    };
+
+    let user = user::Entity::find_by_id(token.user_id).one(db.as_ref()).await?;
+    let (Some(new_email), Some(user)) = (token.payload, user) else {
+        return Ok(result_page(StatusCode::BAD_REQUEST, "This link is no longer valid."));
+    };
+
+    // the email is part of the user's representation, so its ETag has to change as well
+    let version = user.version;
+    let mut user: user::ActiveModel = user.into();
+    user.email = Set(new_email);
+    user.email_verified_at = Set(Some(Utc::now().into()));
+    user.version = Set(version + 1);
+    user.updated_at = Set(Utc::now().into());
+
+    // fails on the unique email if somebody registered the address in the meantime
+    match user.update(db.as_ref()).await {
+        Ok(_) => Ok(result_page(StatusCode::OK, "Your email address was changed.")),
+        Err(err) if matches!(err.sql_err(), Some(SqlErr::UniqueConstraintViolation(_))) => Ok(result_page(
+            StatusCode::CONFLICT,
+            "This email address is already registered.",
+        )),
+        Err(err) => Err(err.into()),
+    }
+}
@@ -0,0 +1,25 @@
+use axum_extra::headers::{ETag, IfMatch};
+
+use crate::error::{AppError, AppResult};
+
+/// The strong ETag of a blog or user, which is its version column.
+pub fn etag(version: i32) -> ETag {
+    format!("\"{}\"", version)
+        .parse()
+        .expect("a quoted number is a valid ETag")
+}
+
+/// Passes when the request has no `If-Match`, `If-Match: *` or one naming `version`.
+pub fn check_if_match(if_match: Option<&IfMatch>, version: i32) -> AppResult<()> {
+    match if_match {
+        Some(if_match) if !if_match.precondition_passes(&etag(version)) => Err(lost_update()),
+        _ => Ok(()),
+    }
+}
+
+/// Somebody else saved the resource since the client read it.
+pub fn lost_update() -> AppError {
+    AppError::PreconditionFailed(
+        "The resource was changed in the meantime, reload it and apply your changes again".to_string(),
+    )
+}
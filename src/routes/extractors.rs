@@ -0,0 +1,303 @@
+use std::{convert::Infallible, net::SocketAddr, sync::Arc};
+
+use axum::{
+    async_trait,
+    extract::{rejection::JsonRejection, ConnectInfo, FromRef, FromRequest, FromRequestParts, Request},
+    http::request::Parts,
+    Json,
+};
+use axum_extra::{
+    headers::{self, authorization::Bearer},
+    TypedHeader,
+};
+use entity::user;
+use http::header;
+use migration::sea_orm::{DatabaseConnection, EntityTrait};
+use serde::de::DeserializeOwned;
+use tower_cookies::Cookies;
+use uuid::Uuid;
+
+use crate::api_tokens::{authenticate_token, ApiScope};
+use crate::jwt::verify_access_token;
+use crate::config::Config;
+use crate::error::{AppError, FieldError};
+use crate::session_store::{ClientInfo, SessionStore};
+use crate::storage::Storage;
+use crate::validation::{Validate, Validator};
+
+use super::auth::renew_session;
+
+/// The user behind the `session_id` cookie or the `Authorization: Bearer` token of the
+/// current request.
+///
+/// Handlers that take this extractor only run for requests carrying a valid,
+/// unexpired session that passed the second factor; everything else is answered
+/// with a 401 problem. JWT access tokens count like a session, personal access
+/// tokens are only accepted on routers that carry an `ApiScope` extension the token
+/// was granted. Behind `require_auth` the user it already resolved is reused.
+#[derive(Clone)]
+pub struct AuthUser(pub user::Model);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for AuthUser
+where
+    S: Send + Sync,
+    Arc<DatabaseConnection>: FromRef<S>,
+    Arc<dyn SessionStore>: FromRef<S>,
+    Arc<Config>: FromRef<S>,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        if let Some(user) = parts.extensions.get::<AuthUser>() {
+            return Ok(user.clone());
+        }
+
+        let db = Arc::<DatabaseConnection>::from_ref(state);
+        let sessions = Arc::<dyn SessionStore>::from_ref(state);
+        let config = Arc::<Config>::from_ref(state);
+
+        if let Some(TypedHeader(authorization)) =
+            Option::<TypedHeader<headers::Authorization<Bearer>>>::from_request_parts(parts, state)
+                .await
+                .unwrap_or(None)
+        {
+            return from_bearer_token(parts, db.as_ref(), &config, authorization.token()).await;
+        }
+
+        // falling back to the cookie would skip the CSRF check, which only exempts bearer requests
+        if parts.headers.contains_key(header::AUTHORIZATION) {
+            return Err(unauthorized("Unsupported authorization scheme"));
+        }
+
+        let cookie = TypedHeader::<headers::Cookie>::from_request_parts(parts, state)
+            .await
+            .map_err(|_| unauthorized("Missing session cookie"))?;
+
+        let session_id = cookie
+            .get("session_id")
+            .ok_or_else(|| unauthorized("Missing session cookie"))?
+            .parse::<Uuid>()
+            .map_err(|_| unauthorized("Invalid session ID"))?;
+
+        let session = sessions
+            .get(session_id)
+            .await?
+            .ok_or_else(|| unauthorized("Session is invalid or expired"))?;
+
+        if session.mfa_pending {
+            return Err(unauthorized("Second factor required"));
+        }
+
+        // activity keeps the session alive
+        let cookies = Cookies::from_request_parts(parts, state)
+            .await
+            .map_err(|(_, message)| AppError::internal(message))?;
+        let session = renew_session(sessions.as_ref(), &config.session.lifetime, &cookies, session)
+            .await
+            .ok_or_else(|| unauthorized("Session is invalid or expired"))?;
+
+        let user = user::Entity::find_by_id(session.user_id)
+            .one(db.as_ref())
+            .await?
+            .ok_or_else(|| unauthorized("User not found"))?;
+
+        Ok(AuthUser(user))
+    }
+}
+
+/// Longer user agents are cut, they are only shown on the session list.
+const MAX_USER_AGENT_LENGTH: usize = 512;
+
+/// Reads the device of a new session from the request. The client address is the peer of
+/// the connection unless the config says a proxy in front sets `X-Forwarded-For`.
+#[async_trait]
+impl<S> FromRequestParts<S> for ClientInfo
+where
+    S: Send + Sync,
+    Arc<Config>: FromRef<S>,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let user_agent = parts
+            .headers
+            .get(header::USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.chars().take(MAX_USER_AGENT_LENGTH).collect());
+
+        let forwarded_for = if Arc::<Config>::from_ref(state).trust_forwarded_for {
+            parts
+                .headers
+                .get("x-forwarded-for")
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.split(',').next())
+                .map(|value| value.trim().to_string())
+        } else {
+            None
+        };
+
+        let ip_address = forwarded_for.or_else(|| {
+            parts
+                .extensions
+                .get::<ConnectInfo<SocketAddr>>()
+                .map(|ConnectInfo(addr)| addr.ip().to_string())
+        });
+
+        Ok(ClientInfo { user_agent, ip_address })
+    }
+}
+
+/// JWT access tokens stand for a full login and pass everywhere the session cookie does,
+/// personal access tokens are limited to their scopes.
+async fn from_bearer_token(
+    parts: &Parts,
+    db: &DatabaseConnection,
+    config: &Config,
+    token: &str,
+) -> Result<AuthUser, AppError> {
+    if let Some(user_id) = verify_access_token(config, token) {
+        return user::Entity::find_by_id(user_id)
+            .one(db)
+            .await?
+            .map(AuthUser)
+            .ok_or_else(|| unauthorized("User not found"));
+    }
+
+    let (user, scopes) = authenticate_token(db, token)
+        .await?
+        .ok_or_else(|| unauthorized("Token is invalid or expired"))?;
+
+    match parts.extensions.get::<ApiScope>() {
+        None => Err(forbidden("This endpoint does not accept API tokens")),
+        Some(scope) if !scopes.contains(scope) => {
+            Err(forbidden(&format!("The token lacks the {} scope", scope)))
+        }
+        Some(_) => Ok(AuthUser(user)),
+    }
+}
+
+fn forbidden(message: &str) -> AppError {
+    AppError::Forbidden(message.to_string())
+}
+
+fn unauthorized(message: &str) -> AppError {
+    AppError::Unauthorized(message.to_string())
+}
+
+/// A JSON body that passed the rules of its `Validate` impl.
+///
+/// Bodies that parse but break the rules, or do not fit the model at all, are answered
+/// with a 422 problem listing every failing field. Malformed JSON stays a 400.
+pub struct ValidatedJson<T>(pub T);
+
+#[async_trait]
+impl<S, T> FromRequest<S> for ValidatedJson<T>
+where
+    S: Send + Sync,
+    T: DeserializeOwned + Validate,
+    Arc<dyn Storage>: FromRef<S>,
+{
+    type Rejection = AppError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let Json(value) = Json::<T>::from_request(req, state)
+            .await
+            .map_err(|rejection| match rejection {
+                JsonRejection::JsonDataError(err) => {
+                    AppError::Validation(vec![FieldError::new("body", err.body_text())])
+                }
+                other => AppError::BadRequest(other.body_text()),
+            })?;
+
+        let storage = Arc::<dyn Storage>::from_ref(state);
+        let mut check = Validator::with_storage(storage.as_ref());
+        value.validate(&mut check);
+        check.finish()?;
+
+        Ok(ValidatedJson(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::{to_bytes, Body},
+        response::IntoResponse,
+    };
+    use http::StatusCode;
+    use serde::Deserialize;
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::storage::InMemoryStorage;
+
+    #[derive(Debug, Deserialize)]
+    struct Signup {
+        name: String,
+        email: String,
+    }
+
+    impl Validate for Signup {
+        fn validate(&self, check: &mut Validator<'_>) {
+            check.not_blank("name", &self.name).email("email", &self.email);
+        }
+    }
+
+    async fn extract(body: &str) -> Result<Signup, AppError> {
+        let storage: Arc<dyn Storage> = Arc::new(InMemoryStorage::default());
+        let req = Request::post("/")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        ValidatedJson::<Signup>::from_request(req, &storage)
+            .await
+            .map(|ValidatedJson(value)| value)
+    }
+
+    async fn problem(err: AppError) -> (StatusCode, String, Value) {
+        let response = err.into_response();
+        let status = response.status();
+        let content_type = response.headers()[header::CONTENT_TYPE].to_str().unwrap().to_string();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, content_type, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn passes_a_valid_body() {
+        let signup = extract(r#"{"name": "Ann", "email": "ann@example.com"}"#).await.unwrap();
+        assert_eq!(signup.name, "Ann");
+    }
+
+    #[tokio::test]
+    async fn lists_every_invalid_field_in_a_422() {
+        let err = extract(r#"{"name": " ", "email": "nope"}"#).await.unwrap_err();
+        let (status, content_type, body) = problem(err).await;
+
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(content_type, "application/problem+json");
+        assert_eq!(body["status"], 422);
+        assert_eq!(
+            body["errors"],
+            json!([
+                {"field": "name", "message": "Must not be empty"},
+                {"field": "email", "message": "Must be a valid email address"},
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn reports_a_mistyped_body_as_a_422() {
+        let err = extract(r#"{"name": 1, "email": "ann@example.com"}"#).await.unwrap_err();
+        let (status, _, body) = problem(err).await;
+
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["errors"][0]["field"], "body");
+    }
+
+    #[tokio::test]
+    async fn reports_broken_json_as_a_400() {
+        let err = extract(r#"{"name": "#).await.unwrap_err();
+        assert!(matches!(err, AppError::BadRequest(_)));
+    }
+}
@@ -1,31 +1,29 @@
 
-use std::time::Duration;
+use std::{sync::Arc, time::Duration};
 
-use aws_sdk_s3::{primitives::ByteStream, Client};
-use axum::{body::Bytes, extract::{DefaultBodyLimit, Multipart, State}, response::{IntoResponse, Response}, routing::post, Router};
-use dotenv::dotenv;
+use axum::{body::Bytes, extract::{DefaultBodyLimit, Multipart, State}, middleware::from_fn_with_state, response::{IntoResponse, Response}, routing::post, Extension, Router};
 use tower::ServiceBuilder;
 use http::{header, StatusCode};
 use ::serde::Serialize;
 use tower_http::{limit::RequestBodyLimitLayer, timeout::TimeoutLayer};
 use uuid::Uuid;
 
-pub async fn configure_aws_s3_client() -> Client{
-    dotenv().ok();
-    let aws_configuration = aws_config::load_from_env().await;
-    let aws_s3_client = aws_sdk_s3::Client::new(&aws_configuration);
-    aws_s3_client
-}
-
-pub async fn upload_router() -> Router{
+use super::middlewares::{require_auth, require_csrf};
+use crate::api_tokens::ApiScope;
+use crate::error::{AppError, AppResult, FieldError};
+use crate::state::AppState;
+use crate::storage::Storage;
 
-    let aws_client = configure_aws_s3_client().await;
+pub fn upload_router(state: &AppState) -> Router<AppState> {
     Router::new()
         .route("/upload", post(upload_hander))
+        // uploads are images for posts, tokens need the blog scope
+        .route_layer(from_fn_with_state(state.clone(), require_csrf))
+        .route_layer(from_fn_with_state(state.clone(), require_auth))
+        .layer(Extension(ApiScope::BlogWrite))
         .layer(DefaultBodyLimit::disable())
         .layer(RequestBodyLimitLayer::new(10 * 1024 * 1023))
         .layer(ServiceBuilder::new().layer(TimeoutLayer::new(Duration::from_secs(120)))) // Set a 2-minute timeout
-        .with_state(aws_client)
 
 }
 
@@ -35,39 +33,49 @@ struct File {
   successful: bool,
   url: String,
   file_name: String,
-  content_type: String,
+  content_type: &'static str,
   #[serde(skip_serializing)]
   bytes: Bytes,
 }
 
 
+/// Uploads are images for posts. The stored objects are served with the content type of
+/// the upload, so anything else, e.g. `text/html`, would let users host pages on our domain.
+const IMAGE_CONTENT_TYPES: [&str; 4] = ["image/png", "image/jpeg", "image/gif", "image/webp"];
+
+fn image_content_type(content_type: Option<&str>) -> Option<&'static str> {
+  let content_type = content_type?.trim();
+  IMAGE_CONTENT_TYPES
+    .into_iter()
+    .find(|allowed| allowed.eq_ignore_ascii_case(content_type))
+}
+
 async fn upload_hander(
-  State(s3_client): State<aws_sdk_s3::Client>,
+  State(storage): State<Arc<dyn Storage>>,
   mut multipart: Multipart,
-) -> Result<Response, Response> {
+) -> AppResult<Response> {
   let mut files = vec![];
-  let bucket_name = std::env::var("AWS_S3_BUCKET").unwrap_or_default();
 
   while let Some(field) = multipart
     .next_field()
     .await
-    .map_err(|err| {
-        eprintln!("Error reading multipart field: {:?}", err);
-        (StatusCode::INTERNAL_SERVER_ERROR, "Something went wrong uploading a file").into_response()
-    })?
+    .map_err(|err| AppError::BadRequest(format!("Invalid multipart body: {}", err)))?
   {
     if let Some("files") = field.name() {
       let file_name = field.file_name().unwrap_or_default().to_owned();
-      let content_type = field.file_name().unwrap_or_default().to_owned();
+      let content_type = image_content_type(field.content_type()).ok_or_else(|| {
+        AppError::Validation(vec![FieldError::new(
+          format!("files[{}]", files.len()),
+          "Must be a PNG, JPEG, GIF or WebP image",
+        )])
+      })?;
       let key = Uuid::new_v4().to_string();
-      let url = format!("https://{bucket_name}.s3.amazonaws.com/{key}");
-    
-      println!("{url}");
+      let url = storage.url(&key);
 
       let bytes = field
         .bytes()
         .await
-        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Was not converted to bytes").into_response())?;
+        .map_err(|err| AppError::BadRequest(format!("Failed to read {}: {}", file_name, err)))?;
 
       files.push(File {
         file_name,
@@ -81,18 +89,13 @@ async fn upload_hander(
   }
 
   for file in &mut files {
-    let body = ByteStream::from(file.bytes.to_vec());
-
-    let res = s3_client
-      .put_object()
-      .bucket(&bucket_name)
-      .content_type(&file.content_type)
-      .content_length(file.bytes.len() as i64)
-      .key(&file.key)
-      .body(body)
-      .send()
+    let res = storage
+      .put(&file.key, file.content_type, file.bytes.clone())
       .await;
 
+    if let Err(err) = &res {
+      tracing::error!("Failed to store {}: {}", file.key, err);
+    }
     file.successful = res.is_ok();
   }
 
@@ -104,4 +107,19 @@ async fn upload_hander(
     )
       .into_response(),
   )
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn only_images_are_accepted() {
+    assert_eq!(image_content_type(Some("image/png")), Some("image/png"));
+    assert_eq!(image_content_type(Some("IMAGE/JPEG")), Some("image/jpeg"));
+
+    for content_type in [None, Some(""), Some("text/html"), Some("image/svg+xml"), Some("photo.png")] {
+      assert_eq!(image_content_type(content_type), None, "{:?}", content_type);
+    }
+  }
+}
@@ -0,0 +1,478 @@
+use std::{
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    extract::State,
+    http::StatusCode,
+    middleware::from_fn_with_state,
+    response::{Html, IntoResponse, Redirect, Response},
+    routing::{get, post},
+    Form, Json, Router,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{Duration, Utc};
+use entity::{recovery_code, totp_credential, user};
+use migration::sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ColumnTrait, Condition, ConnectionTrait, DatabaseConnection,
+    DbErr, EntityTrait, QueryFilter, Set, TransactionTrait,
+};
+use rand::{distributions::Slice, Rng, RngCore};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use totp_rs::{Algorithm, Secret, TOTP};
+use tower_cookies::Cookies;
+use uuid::Uuid;
+
+use super::extractors::AuthUser;
+use super::middlewares::require_csrf;
+use super::oauth_state::{auth_error_page, constant_time_eq};
+use crate::error::{AppError, AppResult};
+use crate::lockout::{Attempt, Lockout};
+use crate::models::user_models::MfaCodeModel;
+use crate::session_store::{SessionError, SessionRecord, SessionStore};
+use crate::state::AppState;
+
+const ISSUER: &str = "Pet Blog";
+const STEP_SECONDS: u64 = 30;
+const RECOVERY_CODE_COUNT: usize = 10;
+const RECOVERY_CODE_ALPHABET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+const MAX_FAILED_ATTEMPTS: i32 = 5;
+const LOCKOUT_MINUTES: i64 = 15;
+
+pub fn mfa_routes(state: &AppState) -> Router<AppState> {
+    Router::new()
+        .route("/me/mfa/totp", post(enroll_totp).delete(disable_totp))
+        .route("/me/mfa/totp/confirm", post(confirm_totp))
+        .route("/me/mfa/recovery_codes", post(regenerate_recovery_codes))
+        .route("/auth/mfa", get(mfa_form).post(complete_mfa))
+        .route_layer(from_fn_with_state(state.clone(), require_csrf))
+}
+
+/// Whether new sessions of the user have to pass the second factor before they count as logged in.
+pub async fn has_second_factor(db: &DatabaseConnection, user_id: Uuid) -> Result<bool, DbErr> {
+    let credential = totp_credential::Entity::find_by_id(user_id).one(db).await?;
+    Ok(credential.is_some_and(|credential| credential.confirmed_at.is_some()))
+}
+
+fn not_enabled() -> AppError {
+    AppError::NotFound("Two-factor authentication is not enabled".to_string())
+}
+
+fn already_enabled() -> AppError {
+    AppError::Conflict("Two-factor authentication is already enabled".to_string())
+}
+
+/// Turns a code that didn't pass into the error of the JSON endpoints.
+fn require_valid(check: CodeCheck) -> AppResult<()> {
+    match check {
+        CodeCheck::Valid => Ok(()),
+        CodeCheck::Invalid => Err(AppError::BadRequest("Invalid code".to_string())),
+        CodeCheck::Locked => Err(AppError::TooManyRequests(
+            "Too many invalid codes, try again later".to_string(),
+        )),
+    }
+}
+
+fn totp(secret: &str, account_name: &str) -> Option<TOTP> {
+    let secret = Secret::Encoded(secret.to_string()).to_bytes().ok()?;
+    TOTP::new(
+        Algorithm::SHA1,
+        6,
+        1,
+        STEP_SECONDS,
+        secret,
+        Some(ISSUER.to_string()),
+        account_name.to_string(),
+    )
+    .ok()
+}
+
+/// Returns the time step the code belongs to. Codes of the previous and the next step are
+/// accepted for clock drift, steps at or before `last_used_step` are refused so a code can't
+/// be replayed.
+fn matching_step(totp: &TOTP, code: &str, last_used_step: Option<i64>) -> Option<i64> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_secs();
+    matching_step_at(totp, code, last_used_step, now)
+}
+
+fn matching_step_at(totp: &TOTP, code: &str, last_used_step: Option<i64>, now: u64) -> Option<i64> {
+    let current = (now / STEP_SECONDS) as i64;
+
+    (current - 1..=current + 1)
+        .filter(|step| last_used_step.is_none_or(|last| *step > last))
+        .find(|step| {
+            let expected = totp.generate(*step as u64 * STEP_SECONDS);
+            constant_time_eq(expected.as_bytes(), code.trim().as_bytes())
+        })
+}
+
+fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_uppercase())
+        .collect()
+}
+
+fn recovery_code_hash(code: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(normalize_recovery_code(code).as_bytes()))
+}
+
+/// Replaces all recovery codes of the user and returns the new ones in clear text. Only their
+/// hashes are stored, the user sees them exactly once.
+async fn replace_recovery_codes<C: ConnectionTrait>(db: &C, user_id: Uuid) -> Result<Vec<String>, DbErr> {
+    recovery_code::Entity::delete_many()
+        .filter(recovery_code::Column::UserId.eq(user_id))
+        .exec(db)
+        .await?;
+
+    let alphabet = Slice::new(RECOVERY_CODE_ALPHABET).expect("alphabet is not empty");
+    let codes: Vec<String> = (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let chars: String = rand::thread_rng()
+                .sample_iter(&alphabet)
+                .take(10)
+                .map(|c| *c as char)
+                .collect();
+            format!("{}-{}", &chars[..5], &chars[5..])
+        })
+        .collect();
+
+    let models = codes.iter().map(|code| recovery_code::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        user_id: Set(user_id),
+        code_hash: Set(recovery_code_hash(code)),
+        used_at: Set(None),
+        ..Default::default()
+    });
+    recovery_code::Entity::insert_many(models).exec(db).await?;
+
+    Ok(codes)
+}
+
+pub enum CodeCheck {
+    Valid,
+    Invalid,
+    Locked,
+}
+
+fn lockout() -> Lockout<totp_credential::Entity> {
+    Lockout {
+        user_id: totp_credential::Column::UserId,
+        failed_attempts: totp_credential::Column::FailedAttempts,
+        locked_until: totp_credential::Column::LockedUntil,
+        max_failed_attempts: MAX_FAILED_ATTEMPTS,
+        duration: Duration::minutes(LOCKOUT_MINUTES),
+    }
+}
+
+/// Checks a TOTP code, or a recovery code when `allow_recovery` is set, and keeps the
+/// lockout counters of the credential up to date.
+///
+/// The attempt is counted before the code is compared and the TOTP step is claimed with a
+/// conditional UPDATE, so neither parallel guesses nor a replayed code slip through.
+async fn check_code(
+    db: &DatabaseConnection,
+    user: &user::Model,
+    credential: totp_credential::Model,
+    code: &str,
+    allow_recovery: bool,
+) -> Result<CodeCheck, DbErr> {
+    let lockout = lockout();
+    match lockout.claim_attempt(db, user.uuid).await? {
+        Attempt::Locked => return Ok(CodeCheck::Locked),
+        Attempt::LastBeforeLock => tracing::warn!("Locking the second factor of user {}", user.uuid),
+        Attempt::Allowed => {}
+    }
+
+    let step = totp(&credential.secret, &user.email)
+        .and_then(|totp| matching_step(&totp, code, credential.last_used_step));
+
+    let valid = match step {
+        // a step can only be used once, a parallel request with the same code loses here
+        Some(step) => {
+            totp_credential::Entity::update_many()
+                .col_expr(totp_credential::Column::LastUsedStep, Expr::value(step))
+                .filter(totp_credential::Column::UserId.eq(user.uuid))
+                .filter(
+                    Condition::any()
+                        .add(totp_credential::Column::LastUsedStep.is_null())
+                        .add(totp_credential::Column::LastUsedStep.lt(step)),
+                )
+                .exec(db)
+                .await?
+                .rows_affected
+                > 0
+        }
+        // marking the code used is conditional, a recovery code can only be redeemed once
+        None if allow_recovery => {
+            recovery_code::Entity::update_many()
+                .col_expr(recovery_code::Column::UsedAt, Expr::value(Utc::now()))
+                .filter(recovery_code::Column::UserId.eq(user.uuid))
+                .filter(recovery_code::Column::CodeHash.eq(recovery_code_hash(code)))
+                .filter(recovery_code::Column::UsedAt.is_null())
+                .exec(db)
+                .await?
+                .rows_affected
+                > 0
+        }
+        None => false,
+    };
+
+    if !valid {
+        return Ok(CodeCheck::Invalid);
+    }
+
+    lockout.reset(db, user.uuid).await?;
+    Ok(CodeCheck::Valid)
+}
+
+/// Checks the second factor of a login that doesn't pass `/auth/mfa`, e.g. the token login.
+pub async fn verify_login_code(db: &DatabaseConnection, user: &user::Model, code: &str) -> Result<CodeCheck, DbErr> {
+    match totp_credential::Entity::find_by_id(user.uuid).one(db).await? {
+        Some(credential) if credential.confirmed_at.is_some() => check_code(db, user, credential, code, true).await,
+        _ => Ok(CodeCheck::Invalid),
+    }
+}
+
+/// Starts the enrollment. The secret only protects logins once a first code was confirmed.
+async fn enroll_totp(
+    AuthUser(user): AuthUser,
+    State(db): State<Arc<DatabaseConnection>>,
+) -> AppResult<impl IntoResponse> {
+    let existing = totp_credential::Entity::find_by_id(user.uuid).one(db.as_ref()).await?;
+    if existing.is_some_and(|credential| credential.confirmed_at.is_some()) {
+        return Err(already_enabled());
+    }
+
+    let mut secret = [0u8; 20];
+    rand::thread_rng().fill_bytes(&mut secret);
+    let Secret::Encoded(secret) = Secret::Raw(secret.to_vec()).to_encoded() else {
+        unreachable!("to_encoded always returns an encoded secret");
+    };
+
+    let totp = totp(&secret, &user.email).ok_or_else(|| {
+        AppError::BadRequest("The email can't be used as a TOTP account name".to_string())
+    })?;
+
+    let enrollment = async {
+        let txn = db.begin().await?;
+
+        totp_credential::Entity::delete_by_id(user.uuid).exec(&txn).await?;
+        totp_credential::ActiveModel {
+            user_id: Set(user.uuid),
+            secret: Set(secret.clone()),
+            confirmed_at: Set(None),
+            last_used_step: Set(None),
+            failed_attempts: Set(0),
+            locked_until: Set(None),
+            ..Default::default()
+        }
+        .insert(&txn)
+        .await?;
+
+        txn.commit().await
+    };
+    enrollment.await?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(json!({
+            "secret": secret,
+            "provisioning_uri": totp.get_url(),
+        })),
+    ))
+}
+
+/// Confirms the enrollment with a first code and hands out the recovery codes.
+async fn confirm_totp(
+    AuthUser(user): AuthUser,
+    State(db): State<Arc<DatabaseConnection>>,
+    Json(confirm): Json<MfaCodeModel>,
+) -> AppResult<impl IntoResponse> {
+    let credential = match totp_credential::Entity::find_by_id(user.uuid).one(db.as_ref()).await? {
+        Some(credential) if credential.confirmed_at.is_some() => return Err(already_enabled()),
+        Some(credential) => credential,
+        None => return Err(AppError::NotFound("Start the enrollment first".to_string())),
+    };
+
+    require_valid(check_code(db.as_ref(), &user, credential, &confirm.code, false).await?)?;
+
+    let confirmation = async {
+        let txn = db.begin().await?;
+
+        totp_credential::Entity::update_many()
+            .col_expr(totp_credential::Column::ConfirmedAt, Expr::value(Utc::now()))
+            .filter(totp_credential::Column::UserId.eq(user.uuid))
+            .exec(&txn)
+            .await?;
+        let codes = replace_recovery_codes(&txn, user.uuid).await?;
+
+        txn.commit().await?;
+        Ok::<_, DbErr>(codes)
+    };
+    let codes = confirmation.await?;
+
+    Ok((StatusCode::OK, Json(json!({ "recovery_codes": codes }))))
+}
+
+/// Turns the second factor off, needs a current TOTP or recovery code.
+async fn disable_totp(
+    AuthUser(user): AuthUser,
+    State(db): State<Arc<DatabaseConnection>>,
+    Json(disable): Json<MfaCodeModel>,
+) -> AppResult<impl IntoResponse> {
+    let credential = totp_credential::Entity::find_by_id(user.uuid)
+        .one(db.as_ref())
+        .await?
+        .ok_or_else(not_enabled)?;
+
+    require_valid(check_code(db.as_ref(), &user, credential, &disable.code, true).await?)?;
+
+    let removal = async {
+        let txn = db.begin().await?;
+
+        recovery_code::Entity::delete_many()
+            .filter(recovery_code::Column::UserId.eq(user.uuid))
+            .exec(&txn)
+            .await?;
+        totp_credential::Entity::delete_by_id(user.uuid).exec(&txn).await?;
+
+        txn.commit().await
+    };
+    removal.await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Replaces the recovery codes, e.g. after they were used up. Needs a current TOTP code.
+async fn regenerate_recovery_codes(
+    AuthUser(user): AuthUser,
+    State(db): State<Arc<DatabaseConnection>>,
+    Json(regenerate): Json<MfaCodeModel>,
+) -> AppResult<impl IntoResponse> {
+    let credential = totp_credential::Entity::find_by_id(user.uuid)
+        .one(db.as_ref())
+        .await?
+        .filter(|credential| credential.confirmed_at.is_some())
+        .ok_or_else(not_enabled)?;
+
+    require_valid(check_code(db.as_ref(), &user, credential, &regenerate.code, false).await?)?;
+
+    let codes = replace_recovery_codes(db.as_ref(), user.uuid).await?;
+    Ok((StatusCode::OK, Json(json!({ "recovery_codes": codes }))))
+}
+
+/// The half-authenticated session behind the `session_id` cookie, if any.
+async fn pending_session(sessions: &dyn SessionStore, cookies: &Cookies) -> Result<Option<SessionRecord>, SessionError> {
+    let Some(session_id) = cookies
+        .get("session_id")
+        .and_then(|cookie| cookie.value().parse::<Uuid>().ok())
+    else {
+        return Ok(None);
+    };
+
+    let session = sessions.get(session_id).await?;
+    Ok(session.filter(|session| session.mfa_pending))
+}
+
+async fn mfa_form(
+    State(sessions): State<Arc<dyn SessionStore>>,
+    cookies: Cookies,
+) -> AppResult<Response> {
+    let Some(session) = pending_session(sessions.as_ref(), &cookies).await? else {
+        return Ok(Redirect::temporary("/login").into_response());
+    };
+
+    Ok(Html(format!(
+        r#"
+           <form action="/auth/mfa" method="post">
+               <input type="hidden" name="csrf_token" value="{}" />
+               <input type="text" name="code" autocomplete="one-time-code" placeholder="Code or recovery code" />
+               <input type="submit" value="Verify" />
+           </form>
+       "#,
+        session.csrf_token
+    ))
+    .into_response())
+}
+
+/// Lifts the `mfa_pending` flag of the current session once the second factor was shown.
+async fn complete_mfa(
+    State(db): State<Arc<DatabaseConnection>>,
+    State(sessions): State<Arc<dyn SessionStore>>,
+    cookies: Cookies,
+    Form(mfa): Form<MfaCodeModel>,
+) -> AppResult<Response> {
+    let Some(mut session) = pending_session(sessions.as_ref(), &cookies).await? else {
+        return Ok(Redirect::temporary("/login").into_response());
+    };
+
+    let found = user::Entity::find_by_id(session.user_id)
+        .find_also_related(totp_credential::Entity)
+        .one(db.as_ref())
+        .await?;
+
+    let Some((user, Some(credential))) = found else {
+        return Ok(auth_error_page(StatusCode::UNAUTHORIZED, "Two-factor authentication is not enabled."));
+    };
+
+    match check_code(db.as_ref(), &user, credential, &mfa.code, true).await? {
+        CodeCheck::Valid => {}
+        CodeCheck::Invalid => return Ok(auth_error_page(StatusCode::UNAUTHORIZED, "Invalid code.")),
+        CodeCheck::Locked => {
+            return Ok(auth_error_page(StatusCode::TOO_MANY_REQUESTS, "Too many invalid codes, try again later."))
+        }
+    }
+
+    session.mfa_pending = false;
+
+    if sessions.update(&session).await? {
+        Ok(Redirect::to("/dashboard").into_response())
+    } else {
+        Ok(Redirect::temporary("/login").into_response())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_700_000_000;
+
+    fn test_totp() -> TOTP {
+        totp("JBSWY3DPEHPK3PXPJBSWY3DPEHPK3PXP", "test@example.com").unwrap()
+    }
+
+    #[test]
+    fn accepts_codes_of_the_neighbouring_steps() {
+        let totp = test_totp();
+        let current = (NOW / STEP_SECONDS) as i64;
+
+        for step in [current - 1, current, current + 1] {
+            let code = totp.generate(step as u64 * STEP_SECONDS);
+            assert_eq!(matching_step_at(&totp, &code, None, NOW), Some(step));
+        }
+
+        let stale = totp.generate((current - 2) as u64 * STEP_SECONDS);
+        assert_eq!(matching_step_at(&totp, &stale, None, NOW), None);
+    }
+
+    #[test]
+    fn refuses_a_replayed_code() {
+        let totp = test_totp();
+        let current = (NOW / STEP_SECONDS) as i64;
+        let code = totp.generate(NOW);
+
+        assert_eq!(matching_step_at(&totp, &code, Some(current - 1), NOW), Some(current));
+        assert_eq!(matching_step_at(&totp, &code, Some(current), NOW), None);
+        assert_eq!(matching_step_at(&totp, &code, Some(current + 1), NOW), None);
+    }
+
+    #[test]
+    fn recovery_codes_ignore_case_and_separators() {
+        assert_eq!(recovery_code_hash("abcd-efgh"), recovery_code_hash("ABCDEFGH"));
+        assert_ne!(recovery_code_hash("abcd-efgh"), recovery_code_hash("abcd-efgi"));
+    }
+}
@@ -1,81 +1,217 @@
 use std::sync::Arc;
 use axum::{
-    body::Body, 
+    body::{to_bytes, Body}, 
+    extract::State,
     middleware::Next, 
     response::{IntoResponse, Redirect, Response},
-    Extension,
 };
-use migration::sea_orm::ColumnTrait;
 
-use axum_extra::{headers, TypedHeader};
-use chrono::Utc;
-use entity::session;
-use http::{header, Request, StatusCode};
-use migration::sea_orm::{DatabaseConnection, EntityTrait, QueryFilter};
+use axum_extra::{
+    headers::{self, authorization::Bearer},
+    TypedHeader,
+};
+use http::{header, HeaderMap, HeaderValue, Method, Request, StatusCode};
+use serde::Deserialize;
+use tower_cookies::Cookies;
 use uuid::Uuid;
 
+use super::auth::renew_session;
+use super::extractors::AuthUser;
+use super::oauth_state::constant_time_eq;
+use crate::config::Config;
+use crate::error::{AppError, REQUEST_ID};
+use crate::session_store::SessionStore;
+
+const X_REQUEST_ID: &str = "x-request-id";
+
+/// Gives every request an id, taken from the `X-Request-Id` header of a proxy in front or
+/// generated. It is echoed in the response header and in the body of every `AppError`.
+pub async fn request_id(request: Request<Body>, next: Next) -> Response {
+    let id = request
+        .headers()
+        .get(X_REQUEST_ID)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| !id.is_empty() && id.len() <= 64 && id.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-'))
+        .map(str::to_string)
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+
+    let header_value = HeaderValue::from_str(&id).expect("request ids are plain ascii");
+    let mut response = REQUEST_ID.scope(id, next.run(request)).await;
+    response.headers_mut().insert(X_REQUEST_ID, header_value);
+    response
+}
+
 pub async fn user_expired(
-    Extension(db): Extension<Arc<DatabaseConnection>>,
+    State(sessions): State<Arc<dyn SessionStore>>,
+    State(config): State<Arc<Config>>,
+    cookies: Cookies,
     cookie: Option<TypedHeader<headers::Cookie>>,
     request: Request<Body>,
     next: Next,
-) -> Result<Response, StatusCode> {
+) -> Result<Response, AppError> {
     if let Some(cookie) = cookie {
         if let Some(session_id) = cookie.get("session_id") {
 
             let session_id = match session_id.parse::<Uuid>() {
                 Ok(uuid) => uuid,
-                Err(_) => {
-                    println!("Failed to parse session_id as UUID");
-                    return Ok((StatusCode::BAD_REQUEST, "Invalid session ID").into_response());
+                Err(err) => {
+                    tracing::warn!("Rejected a session_id cookie that is not a UUID: {}", err);
+                    let mut response = AppError::Unauthorized("Invalid session".to_string()).into_response();
+                    clear_session_cookies(&mut response);
+                    return Ok(response);
                 }
             };
 
-            let session = match entity::session::Entity::find()
-                .filter(session::Column::SessionId.eq(session_id))
-                .one(db.as_ref())
-                .await
-            {
-                Ok(Some(session)) => session,
-                Ok(None) => return Ok((StatusCode::UNAUTHORIZED,  Redirect::temporary("/login")).into_response()),
-                Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR),
-            };
+            // the store hides unknown and expired sessions alike and removes expired ones
+            let session = sessions.get(session_id).await?;
 
-            println!("Expires_at : {:?}", session.expires_at);
-
-            if session.expires_at < Utc::now() {
-                let result = entity::session::Entity::delete_by_id(session.session_id)
-                    .exec(db.as_ref())
-                    .await;
-
-                match result {
-                    Ok(delete_result) => {
-                        if delete_result.rows_affected > 0 {
-                            println!("Session successfully deleted from the database.");
-                            Redirect::temporary("/login").into_response();
-                        } else {
-                            println!("No session found with the given session_id.");
-                        }
-                    }
-                    Err(err) => {
-                        println!("Error deleting session from database: {:?}", err);
-                    }
-                }
+            let path = request.uri().path();
+            let is_login_path = path.starts_with("/auth/") || path == "/login" || path == "/logout";
 
-                // Clear the session cookie by setting it with an expiration in the past
-                let mut response = (StatusCode::UNAUTHORIZED, Redirect::temporary("/login")).into_response();
-                let headers = response.headers_mut();
-                headers.insert(
-                    header::SET_COOKIE,
-                    "session_id=deleted; HttpOnly; Secure; SameSite=Strict; Max-Age=0"
-                        .parse()
-                        .unwrap(),
-                );
+            let Some(session) = session else {
+                let mut response = if is_login_path {
+                    next.run(request).await
+                } else {
+                    Redirect::temporary("/login").into_response()
+                };
+                clear_session_cookies(&mut response);
                 return Ok(response);
+            };
+
+            // half-authenticated sessions may only finish or restart the login
+            if session.mfa_pending && !is_login_path {
+                return Ok(Redirect::temporary("/auth/mfa").into_response());
             }
+
+            // activity keeps the session alive
+            renew_session(sessions.as_ref(), &config.session.lifetime, &cookies, session).await;
         }
     }
 
     // Proceed with the next middleware or handler if the session is still valid
     Ok(next.run(request).await)
 }
+
+/// Clears the session cookies by setting them with an expiration in the past, on the same
+/// path they were set with.
+pub fn clear_session_cookies(response: &mut Response) {
+    for cookie in [
+        "session_id=deleted; Path=/; HttpOnly; Secure; SameSite=Strict; Max-Age=0",
+        "csrf_token=deleted; Path=/; Secure; SameSite=Strict; Max-Age=0",
+    ] {
+        response
+            .headers_mut()
+            .append(header::SET_COOKIE, HeaderValue::from_static(cookie));
+    }
+}
+
+fn wants_html(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::ACCEPT)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|accept| accept.contains("text/html"))
+}
+
+/// Rejects anonymous requests to the routes it is layered on.
+///
+/// Browsers asking for HTML are sent to the login page, API clients get the problem of
+/// `AuthUser`. The resolved user is put into the request extensions, where `AuthUser` picks
+/// it up again without a second lookup.
+pub async fn require_auth(
+    auth: Result<AuthUser, AppError>,
+    mut request: Request<Body>,
+    next: Next,
+) -> Response {
+    let user = match auth {
+        Ok(user) => user,
+        Err(AppError::Unauthorized(_)) if wants_html(request.headers()) => {
+            return Redirect::temporary("/login").into_response();
+        }
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    request.extensions_mut().insert(user);
+    next.run(request).await
+}
+
+/// Largest urlencoded form `require_csrf` reads to find the `csrf_token` field.
+const MAX_CSRF_FORM_SIZE: usize = 64 * 1024;
+
+#[derive(Deserialize)]
+struct CsrfForm {
+    csrf_token: Option<String>,
+}
+
+/// Double-submit CSRF check for state-changing requests authenticated by the session cookie.
+///
+/// The CSRF token of the session has to come back in the `X-CSRF-Token` header or, for HTML
+/// forms, in a `csrf_token` field. Safe methods, bearer requests and requests without a
+/// session pass, the handlers reject the latter where a login is needed. Only a well-formed
+/// `Bearer` header counts, `AuthUser` refuses any other scheme instead of using the cookie.
+pub async fn require_csrf(
+    State(sessions): State<Arc<dyn SessionStore>>,
+    cookie: Option<TypedHeader<headers::Cookie>>,
+    bearer: Option<TypedHeader<headers::Authorization<Bearer>>>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    let is_safe = matches!(*request.method(), Method::GET | Method::HEAD | Method::OPTIONS);
+    if is_safe || bearer.is_some() {
+        return next.run(request).await;
+    }
+
+    let Some(session_id) = cookie
+        .as_ref()
+        .and_then(|cookie| cookie.get("session_id"))
+        .and_then(|session_id| session_id.parse::<Uuid>().ok())
+    else {
+        return next.run(request).await;
+    };
+
+    let session = match sessions.get(session_id).await {
+        Ok(Some(session)) => session,
+        Ok(None) => return next.run(request).await,
+        Err(err) => return AppError::from(err).into_response(),
+    };
+
+    let (request, token) = match csrf_token_of(request).await {
+        Ok(found) => found,
+        Err(response) => return response,
+    };
+
+    match token {
+        Some(token) if constant_time_eq(token.as_bytes(), session.csrf_token.as_bytes()) => next.run(request).await,
+        _ => {
+            tracing::warn!("Rejected a request without a valid CSRF token for session {}", session.session_id);
+            AppError::Forbidden("CSRF token missing or invalid".to_string()).into_response()
+        }
+    }
+}
+
+/// Takes the token from the header, or from the body of an urlencoded form. The body is
+/// put back for the handler.
+async fn csrf_token_of(request: Request<Body>) -> Result<(Request<Body>, Option<String>), Response> {
+    if let Some(token) = request.headers().get("x-csrf-token").and_then(|value| value.to_str().ok()) {
+        let token = token.to_string();
+        return Ok((request, Some(token)));
+    }
+
+    let is_form = request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|content_type| content_type.starts_with("application/x-www-form-urlencoded"));
+    if !is_form {
+        return Ok((request, None));
+    }
+
+    let (parts, body) = request.into_parts();
+    let bytes = to_bytes(body, MAX_CSRF_FORM_SIZE)
+        .await
+        .map_err(|_| (StatusCode::PAYLOAD_TOO_LARGE, "Form is too large").into_response())?;
+    let token = serde_urlencoded::from_bytes::<CsrfForm>(&bytes)
+        .ok()
+        .and_then(|form| form.csrf_token);
+
+    Ok((Request::from_parts(parts, Body::from(bytes)), token))
+}
@@ -1,46 +1,65 @@
-use std::sync::Arc;
-
 use auth::auth_user_routes;
-use axum::{routing::get_service, Router};
-use migration::sea_orm::DatabaseConnection;
-use registration::register_routing;
+use axum::{middleware::from_fn, routing::get_service, Router};
 use tower_cookies::CookieManagerLayer;
 use tower_http::{cors::{AllowHeaders, CorsLayer}, services::ServeDir};
-use http::{HeaderValue, Method};
+use http::{HeaderName, Method};
+
+use crate::state::AppState;
+use middlewares::request_id;
 
 pub mod user;
+pub mod api_tokens;
 pub mod blog;
 pub mod auth;
-pub mod registration;
 pub mod middlewares;
+pub mod mfa;
 pub mod file_upload;
+pub mod extractors;
+pub mod email;
+pub mod etag;
+pub mod oauth_state;
+pub mod pagination;
+pub mod password;
+pub mod providers;
+pub mod sessions;
+pub mod token_auth;
 
 
 
-pub async fn create_all_routes(db: Arc<DatabaseConnection>) -> Router {
-
+pub fn create_all_routes(state: AppState) -> Router {
     let cors = CorsLayer::new()
-        .allow_methods([Method::POST, Method::GET, Method::PUT, Method::DELETE])
-        .allow_origin("http://localhost:3010".parse::<HeaderValue>().unwrap())
+        .allow_methods([Method::POST, Method::GET, Method::PUT, Method::PATCH, Method::DELETE])
+        .allow_origin(state.config().cors_origins.clone())
         .allow_credentials(true)
         .allow_headers(AllowHeaders::list(vec![
             "Content-Type".parse().unwrap(),
             "Authorization".parse().unwrap(),
-        ]));
-
+            "X-CSRF-Token".parse().unwrap(),
+            "If-Match".parse().unwrap(),
+            "X-Request-Id".parse().unwrap(),
+        ]))
+        .expose_headers([
+            "x-request-id".parse::<HeaderName>().unwrap(),
+            "etag".parse::<HeaderName>().unwrap(),
+            "link".parse::<HeaderName>().unwrap(),
+        ]);
 
-    //if there is gotta be more than ONE db is better to use AppState, using the Extension will cause lot of errors 
-    //the programm will not understand which db it should use, it will vary between those two 
     Router::new()
-        .merge(auth_user_routes(db.clone()))
-        .merge(register_routing(db.clone()))
-        .merge(user::user_routes(db.clone()))
-        .merge(blog::blog_routes(db))
-        .merge(file_upload::upload_router().await)
+        .merge(auth_user_routes(&state))
+        .merge(password::password_routes(&state))
+        .merge(email::email_routes(&state))
+        .merge(mfa::mfa_routes(&state))
+        .merge(api_tokens::api_token_routes(&state))
+        .merge(sessions::session_routes(&state))
+        .merge(token_auth::token_auth_routes())
+        .merge(user::user_routes(&state))
+        .merge(blog::blog_routes(&state))
+        .merge(file_upload::upload_router(&state))
         .layer(cors)
         .layer(CookieManagerLayer::new())
-        //.with_state(AppState::default())
-        .fallback_service(routes_static())       
+        .layer(from_fn(request_id))
+        .fallback_service(routes_static())
+        .with_state(state)
 }
 
 fn routes_static() -> Router {
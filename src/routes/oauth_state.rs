@@ -0,0 +1,111 @@
+use axum::{
+    http::StatusCode,
+    response::{Html, IntoResponse, Response},
+};
+use tower_cookies::{cookie::SameSite, Cookie, Cookies};
+use uuid::Uuid;
+
+use crate::redis_manager::auth_state::{store_pending_auth, take_pending_auth, PendingAuth};
+use crate::redis_manager::pool::RedisPool;
+
+const PRE_AUTH_COOKIE: &str = "pre_auth_id";
+
+/// Remembers the CSRF token, nonce and PKCE verifier of a login attempt in redis and binds
+/// them to this browser through a short lived `pre_auth_id` cookie.
+pub async fn begin_auth(redis: &RedisPool, cookies: &Cookies, pending: PendingAuth) -> Result<(), Response> {
+    let pre_auth_id = Uuid::new_v4().to_string();
+
+    if let Err(err) = store_pending_auth(redis, &pre_auth_id, &pending).await {
+        tracing::error!("Failed to store the OAuth state in Redis: {:?}", err);
+        return Err(auth_error_page(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Could not start the login, please try again later.",
+        ));
+    }
+
+    // Lax, because the provider sends the user back to us with a cross-site redirect
+    let mut cookie = Cookie::new(PRE_AUTH_COOKIE, pre_auth_id);
+    cookie.set_http_only(true);
+    cookie.set_path("/");
+    cookie.set_secure(true);
+    cookie.set_same_site(SameSite::Lax);
+    cookie.set_max_age(tower_cookies::cookie::time::Duration::minutes(10));
+    cookies.add(cookie);
+
+    Ok(())
+}
+
+/// Checks the `state` returned by the provider against the one stored by [`begin_auth`]
+/// and makes sure the callback belongs to the provider the login was started with.
+///
+/// The stored state is consumed whatever the outcome, so replaying a callback always fails.
+/// On success the pending login is returned, it carries the PKCE verifier and nonce for the code exchange.
+pub async fn verify_auth_state(
+    redis: &RedisPool,
+    cookies: &Cookies,
+    provider: &str,
+    state: Option<&String>,
+) -> Result<PendingAuth, Response> {
+    let pre_auth_id = cookies.get(PRE_AUTH_COOKIE).map(|c| c.value().to_string());
+
+    let mut removal = Cookie::from(PRE_AUTH_COOKIE);
+    removal.set_path("/");
+    cookies.remove(removal);
+
+    let (Some(pre_auth_id), Some(state)) = (pre_auth_id, state) else {
+        return Err(invalid_state());
+    };
+
+    let pending = match take_pending_auth(redis, &pre_auth_id).await {
+        Ok(Some(pending)) => pending,
+        Ok(None) => return Err(invalid_state()),
+        Err(err) => {
+            tracing::error!("Failed to read the OAuth state from Redis: {:?}", err);
+            return Err(auth_error_page(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Could not verify the login, please try again later.",
+            ));
+        }
+    };
+
+    if !constant_time_eq(pending.csrf_state.as_bytes(), state.as_bytes()) {
+        tracing::warn!("OAuth state mismatch for pre_auth_id {}", pre_auth_id);
+        return Err(invalid_state());
+    }
+
+    if pending.provider != provider {
+        tracing::warn!(
+            "OAuth provider mismatch for pre_auth_id {}: started with {}, returned from {}",
+            pre_auth_id, pending.provider, provider
+        );
+        return Err(invalid_state());
+    }
+
+    Ok(pending)
+}
+
+fn invalid_state() -> Response {
+    auth_error_page(
+        StatusCode::BAD_REQUEST,
+        "This login link is invalid, expired or was already used.",
+    )
+}
+
+pub fn auth_error_page(status: StatusCode, message: &str) -> Response {
+    (
+        status,
+        Html(format!(
+            r#"
+            <h1>Login failed</h1>
+            <p>{}</p>
+            <a href="/login">Back to login</a>
+        "#,
+            message
+        )),
+    )
+        .into_response()
+}
+
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
@@ -0,0 +1,134 @@
+use std::{fmt::Display, str::FromStr};
+
+use axum::http::HeaderValue;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, FixedOffset};
+use migration::sea_orm::{
+    sea_query::{Expr, SimpleExpr},
+    ColumnTrait, Order, Value,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{AppError, AppResult};
+
+pub const DEFAULT_PAGE_SIZE: u64 = 20;
+pub const MAX_PAGE_SIZE: u64 = 100;
+
+#[derive(Clone, Copy, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    /// Newest first.
+    #[default]
+    Desc,
+}
+
+impl SortOrder {
+    pub fn order(self) -> Order {
+        match self {
+            SortOrder::Asc => Order::Asc,
+            SortOrder::Desc => Order::Desc,
+        }
+    }
+}
+
+pub fn page_size(limit: Option<u64>) -> u64 {
+    limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE)
+}
+
+/// The position after the last row of a page, `created_at` first and the id to break ties.
+///
+/// Clients get it as an opaque token and must not build one themselves, the format may
+/// change.
+pub struct Cursor<K> {
+    pub created_at: DateTime<FixedOffset>,
+    pub id: K,
+}
+
+impl<K: Display + FromStr> Cursor<K> {
+    pub fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(format!("{}|{}", self.created_at.to_rfc3339(), self.id))
+    }
+
+    pub fn decode(token: &str) -> AppResult<Self> {
+        let invalid = || AppError::BadRequest("Invalid cursor".to_string());
+
+        let decoded = URL_SAFE_NO_PAD.decode(token).map_err(|_| invalid())?;
+        let decoded = String::from_utf8(decoded).map_err(|_| invalid())?;
+        let (created_at, id) = decoded.split_once('|').ok_or_else(invalid)?;
+
+        Ok(Cursor {
+            created_at: DateTime::parse_from_rfc3339(created_at).map_err(|_| invalid())?,
+            id: id.parse().map_err(|_| invalid())?,
+        })
+    }
+
+    /// Rows after the cursor in `order`, compared as a `(created_at, id)` row so the
+    /// composite index serves it.
+    pub fn after<C: ColumnTrait>(&self, created_at: C, id: C, order: SortOrder) -> SimpleExpr
+    where
+        K: Clone + Into<Value>,
+    {
+        let columns = Expr::tuple([Expr::col(created_at).into(), Expr::col(id).into()]);
+        let values = Expr::tuple([
+            Expr::val(self.created_at).into(),
+            Expr::val(self.id.clone()).into(),
+        ]);
+        match order {
+            SortOrder::Asc => columns.gt(values),
+            SortOrder::Desc => columns.lt(values),
+        }
+    }
+}
+
+/// The `Link: <...>; rel="next"` header for the page after this one, `query` is the
+/// query string of the request with the next cursor in place.
+pub fn next_link(base_url: &str, path: &str, query: &impl Serialize) -> Option<HeaderValue> {
+    let query = serde_urlencoded::to_string(query).ok()?;
+    HeaderValue::from_str(&format!("<{}{}?{}>; rel=\"next\"", base_url, path, query)).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+
+    #[test]
+    fn cursor_round_trips() {
+        let cursor = Cursor {
+            created_at: DateTime::parse_from_rfc3339("2024-05-01T12:30:45.123456+02:00").unwrap(),
+            id: Uuid::new_v4(),
+        };
+
+        let decoded = Cursor::<Uuid>::decode(&cursor.encode()).unwrap();
+        assert_eq!(decoded.created_at, cursor.created_at);
+        assert_eq!(decoded.id, cursor.id);
+    }
+
+    #[test]
+    fn cursor_rejects_malformed_tokens() {
+        let tokens = [
+            "not base64!".to_string(),
+            URL_SAFE_NO_PAD.encode([0xff, 0xfe]),
+            URL_SAFE_NO_PAD.encode("2024-05-01T12:30:45Z"),
+            URL_SAFE_NO_PAD.encode("yesterday|42"),
+            URL_SAFE_NO_PAD.encode("2024-05-01T12:30:45Z|not-a-number"),
+        ];
+
+        for token in tokens {
+            assert!(
+                matches!(Cursor::<i32>::decode(&token), Err(AppError::BadRequest(_))),
+                "{}",
+                token
+            );
+        }
+    }
+
+    #[test]
+    fn page_size_is_clamped() {
+        assert_eq!(page_size(None), DEFAULT_PAGE_SIZE);
+        assert_eq!(page_size(Some(0)), 1);
+        assert_eq!(page_size(Some(1_000)), MAX_PAGE_SIZE);
+    }
+}
@@ -0,0 +1,452 @@
+use std::{collections::HashMap, sync::Arc};
+
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    middleware::from_fn_with_state,
+    response::{Html, IntoResponse, Response},
+    routing::{get, post},
+    Form, Json, Router,
+};
+use axum_extra::{headers, TypedHeader};
+use chrono::{Duration, Utc};
+use entity::{
+    password_credential,
+    sea_orm_active_enums::{AuthTokenKind, Role},
+    user,
+};
+use migration::sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr, EntityTrait,
+    QueryFilter, Set, TransactionTrait,
+};
+use serde_json::json;
+use tower_cookies::Cookies;
+use uuid::Uuid;
+
+use super::auth::start_session;
+use super::middlewares::require_csrf;
+use super::email::send_verification_email;
+use super::extractors::{AuthUser, ValidatedJson};
+use super::mfa::has_second_factor;
+use super::oauth_state::auth_error_page;
+use super::sessions::{current_session, end_other_sessions};
+use super::token_auth::revoke_user_refresh_tokens;
+use crate::auth_tokens::{consume_token, issue_token};
+use crate::mailer::{Email, Mailer};
+use crate::config::Config;
+use crate::error::{AppError, AppResult};
+use crate::lockout::{Attempt, Lockout};
+use crate::session_store::{ClientInfo, SessionStore};
+use crate::models::user_models::{
+    ChangePasswordModel, CreateUserModel, ForgotPasswordModel, LoginModel, ResetPasswordModel,
+};
+use crate::state::AppState;
+use crate::validation::{Validator, MAX_PASSWORD_LENGTH, MIN_PASSWORD_LENGTH};
+
+// after this many wrong passwords in a row the account is locked for LOCKOUT_MINUTES
+const MAX_FAILED_ATTEMPTS: i32 = 5;
+const LOCKOUT_MINUTES: i64 = 15;
+
+const RESET_TTL_HOURS: i64 = 1;
+
+// how long after a login a provider-only user may set a first password
+const REAUTH_WINDOW_MINUTES: i64 = 10;
+
+pub fn password_routes(state: &AppState) -> Router<AppState> {
+    Router::new()
+        .route("/auth/password/register", post(register))
+        .route("/auth/password/login", post(login))
+        .route("/auth/password/change", post(change_password).route_layer(from_fn_with_state(state.clone(), require_csrf)))
+        .route("/auth/password/forgot", post(forgot_password))
+        .route("/auth/password/reset", get(reset_password_form).post(reset_password))
+}
+
+/// Hashes a password with Argon2id and a random salt, off the async runtime.
+pub async fn hash_password(password: String) -> Result<String, argon2::password_hash::Error> {
+    tokio::task::spawn_blocking(move || {
+        let salt = SaltString::generate(&mut OsRng);
+        Argon2::default()
+            .hash_password(password.as_bytes(), &salt)
+            .map(|hash| hash.to_string())
+    })
+    .await
+    .expect("Password hashing task panicked")
+}
+
+async fn verify_password(password: String, password_hash: String) -> bool {
+    tokio::task::spawn_blocking(move || {
+        PasswordHash::new(&password_hash)
+            .map(|hash| Argon2::default().verify_password(password.as_bytes(), &hash).is_ok())
+            .unwrap_or(false)
+    })
+    .await
+    .unwrap_or(false)
+}
+
+fn weak_password(password: &str) -> AppResult<()> {
+    let mut check = Validator::default();
+    check.password("password", password);
+    check.finish()
+}
+
+/// Creates a user together with its password credential in one transaction. The caller
+/// has validated `user_data` already.
+async fn create_local_account(
+    db: &DatabaseConnection,
+    user_data: &CreateUserModel,
+) -> AppResult<user::Model> {
+
+    let existing = user::Entity::find()
+        .filter(user::Column::Email.eq(user_data.email.clone()))
+        .one(db)
+        .await?;
+
+    // never attach a password to an existing (e.g. OAuth) account, that would hand it over
+    if existing.is_some() {
+        return Err(AppError::Conflict("Email is already registered".to_string()));
+    }
+
+    let password_hash = hash_password(user_data.password.clone())
+        .await
+        .map_err(|err| AppError::internal(err.to_string()))?;
+
+    let insert = async {
+        let txn = db.begin().await?;
+
+        let user = user::ActiveModel {
+            name: Set(user_data.name.to_owned()),
+            email: Set(user_data.email.to_owned()),
+            uuid: Set(Uuid::new_v4()),
+            role: Set(Role::Author),
+            email_verified_at: Set(None),
+            ..Default::default()
+        }
+        .insert(&txn)
+        .await?;
+
+        password_credential::ActiveModel {
+            user_id: Set(user.uuid),
+            password_hash: Set(password_hash),
+            failed_attempts: Set(0),
+            ..Default::default()
+        }
+        .insert(&txn)
+        .await?;
+
+        txn.commit().await?;
+        Ok::<_, DbErr>(user)
+    };
+
+    Ok(insert.await?)
+}
+
+/// Signs up a local account, signs it in and mails the link to verify the address.
+pub async fn register(
+    State(db): State<Arc<DatabaseConnection>>,
+    State(mailer): State<Arc<dyn Mailer>>,
+    State(sessions): State<Arc<dyn SessionStore>>,
+    State(config): State<Arc<Config>>,
+    client: ClientInfo,
+    cookies: Cookies,
+    ValidatedJson(user_data): ValidatedJson<CreateUserModel>,
+) -> AppResult<impl IntoResponse> {
+    let user = create_local_account(db.as_ref(), &user_data).await?;
+
+    if let Err(err) = send_verification_email(db.as_ref(), &config, mailer.as_ref(), &user).await {
+        tracing::error!("Failed to issue the verification token: {:?}", err);
+    }
+
+    let session = config.session.lifetime.new_session(user.uuid, false, false, client);
+    start_session(sessions.as_ref(), &cookies, session).await?;
+
+    Ok((StatusCode::CREATED, Json(json!({ "uuid": user.uuid }))))
+}
+
+fn lockout() -> Lockout<password_credential::Entity> {
+    Lockout {
+        user_id: password_credential::Column::UserId,
+        failed_attempts: password_credential::Column::FailedAttempts,
+        locked_until: password_credential::Column::LockedUntil,
+        max_failed_attempts: MAX_FAILED_ATTEMPTS,
+        duration: Duration::minutes(LOCKOUT_MINUTES),
+    }
+}
+
+/// Checks email and password with the lockout rules, shared by the cookie and the token login.
+pub async fn authenticate_password(db: &DatabaseConnection, login_data: LoginModel) -> AppResult<user::Model> {
+    let invalid_credentials = || AppError::Unauthorized("Invalid email or password".to_string());
+
+    let found = user::Entity::find()
+        .filter(user::Column::Email.eq(login_data.email.clone()))
+        .find_also_related(password_credential::Entity)
+        .one(db)
+        .await?;
+
+    let Some((user, Some(credential))) = found else {
+        return Err(invalid_credentials());
+    };
+
+    if !check_password(db, &credential, login_data.password).await? {
+        return Err(invalid_credentials());
+    }
+
+    Ok(user)
+}
+
+/// Verifies a password against the credential under the lockout rules. Every password check
+/// goes through here, otherwise the unlocked one could be used to guess without a limit.
+async fn check_password(
+    db: &DatabaseConnection,
+    credential: &password_credential::Model,
+    password: String,
+) -> AppResult<bool> {
+    // counted before the hash is checked, so parallel guesses can't outrun the lock
+    let lockout = lockout();
+    match lockout.claim_attempt(db, credential.user_id).await? {
+        Attempt::Locked => {
+            return Err(AppError::TooManyRequests(
+                "Too many failed logins, try again later".to_string(),
+            ))
+        }
+        Attempt::LastBeforeLock => {
+            tracing::warn!("Locking the password login of user {}", credential.user_id)
+        }
+        Attempt::Allowed => {}
+    }
+
+    if !verify_password(password, credential.password_hash.clone()).await {
+        return Ok(false);
+    }
+
+    lockout.reset(db, credential.user_id).await?;
+    Ok(true)
+}
+
+async fn login(
+    State(db): State<Arc<DatabaseConnection>>,
+    State(sessions): State<Arc<dyn SessionStore>>,
+    State(config): State<Arc<Config>>,
+    client: ClientInfo,
+    cookies: Cookies,
+    Json(login_data): Json<LoginModel>,
+) -> AppResult<impl IntoResponse> {
+    let remember_me = login_data.remember_me;
+    let user = authenticate_password(db.as_ref(), login_data).await?;
+    let mfa_pending = has_second_factor(db.as_ref(), user.uuid).await?;
+
+    let session = config.session.lifetime.new_session(user.uuid, mfa_pending, remember_me, client);
+    start_session(sessions.as_ref(), &cookies, session).await?;
+
+    // with a second factor the client has to post the code to /auth/mfa next
+    Ok((StatusCode::OK, Json(json!({ "uuid": user.uuid, "mfa_required": mfa_pending }))))
+}
+
+/// Changes the password of the logged in user and signs out every other device, only the
+/// session making the request stays.
+///
+/// Users that only signed in through a provider so far can set a first password without
+/// `current_password`, but only right after a login, so a session left open on a shared
+/// computer can't be turned into a password login.
+async fn change_password(
+    AuthUser(user): AuthUser,
+    State(db): State<Arc<DatabaseConnection>>,
+    State(sessions): State<Arc<dyn SessionStore>>,
+    cookie: Option<TypedHeader<headers::Cookie>>,
+    ValidatedJson(change): ValidatedJson<ChangePasswordModel>,
+) -> AppResult<impl IntoResponse> {
+    let current = current_session(sessions.as_ref(), cookie, &user).await?;
+
+    let credential = password_credential::Entity::find_by_id(user.uuid)
+        .one(db.as_ref())
+        .await?;
+
+    match &credential {
+        Some(credential) => {
+            let current_password = change.current_password.unwrap_or_default();
+            if !check_password(db.as_ref(), credential, current_password).await? {
+                return Err(AppError::Unauthorized("Current password is wrong".to_string()));
+            }
+        }
+        None => {
+            let fresh_login = current.as_ref().is_some_and(|session| {
+                Utc::now() - session.created_at < Duration::minutes(REAUTH_WINDOW_MINUTES)
+            });
+            if !fresh_login {
+                return Err(AppError::Forbidden(
+                    "Sign in again to set a password".to_string(),
+                ));
+            }
+        }
+    }
+
+    let password_hash = hash_password(change.new_password)
+        .await
+        .map_err(|err| AppError::internal(err.to_string()))?;
+
+    match credential {
+        Some(credential) => {
+            let mut credential: password_credential::ActiveModel = credential.into();
+            credential.password_hash = Set(password_hash);
+            credential.failed_attempts = Set(0);
+            credential.locked_until = Set(None);
+            credential.updated_at = Set(Utc::now().into());
+            credential.update(db.as_ref()).await?;
+        }
+        None => {
+            password_credential::ActiveModel {
+                user_id: Set(user.uuid),
+                password_hash: Set(password_hash),
+                failed_attempts: Set(0),
+                ..Default::default()
+            }
+            .insert(db.as_ref())
+            .await?;
+        }
+    }
+
+    let current = current.map(|session| session.session_id);
+    end_other_sessions(db.as_ref(), sessions.as_ref(), user.uuid, current).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Mails a reset link. Always answers 202 so the endpoint can't be used to probe for accounts.
+async fn forgot_password(
+    State(db): State<Arc<DatabaseConnection>>,
+    State(config): State<Arc<Config>>,
+    State(mailer): State<Arc<dyn Mailer>>,
+    ValidatedJson(forgot): ValidatedJson<ForgotPasswordModel>,
+) -> AppResult<impl IntoResponse> {
+    let accepted = (StatusCode::ACCEPTED, "If the account exists a reset link was sent");
+
+    let Some(user) = user::Entity::find()
+        .filter(user::Column::Email.eq(forgot.email))
+        .one(db.as_ref())
+        .await?
+    else {
+        return Ok(accepted);
+    };
+
+    let token = issue_token(
+        db.as_ref(),
+        &config,
+        user.uuid,
+        AuthTokenKind::PasswordReset,
+        None,
+        Duration::hours(RESET_TTL_HOURS),
+    )
+    .await?;
+
+    let email = Email {
+        to: user.email.clone(),
+        subject: "Reset your password".to_string(),
+        body: format!(
+            "Hi {},\n\nyou can choose a new password here:\n{}/auth/password/reset?token={}\n\nThe link expires in {} hour. If you did not ask for it, just ignore this mail.",
+            user.name,
+            config.public_base_url,
+            token,
+            RESET_TTL_HOURS
+        ),
+    };
+
+    if let Err(err) = mailer.send(email).await {
+        tracing::error!("Failed to send the reset mail to user {}: {}", user.uuid, err);
+    }
+
+    Ok(accepted)
+}
+
+async fn reset_password_form(Query(params): Query<HashMap<String, String>>) -> impl IntoResponse {
+    let token = params.get("token").cloned().unwrap_or_default();
+
+    Html(format!(
+        r#"
+           <form action="/auth/password/reset" method="post">
+               <input type="hidden" name="token" value="{}" />
+               <input type="password" name="new_password" placeholder="New password" />
+               <input type="submit" value="Reset Password" />
+           </form>
+       "#,
+        html_escape(&token)
+    ))
+}
+
+/// Redeems a reset token. Proving access to the mailbox also verifies the address, and all
+/// existing sessions of the user are ended.
+async fn reset_password(
+    State(db): State<Arc<DatabaseConnection>>,
+    State(config): State<Arc<Config>>,
+    State(sessions): State<Arc<dyn SessionStore>>,
+    Form(reset): Form<ResetPasswordModel>,
+) -> AppResult<Response> {
+    if weak_password(&reset.new_password).is_err() {
+        return Ok(auth_error_page(
+            StatusCode::BAD_REQUEST,
+            &format!(
+                "Password must have between {} and {} characters",
+                MIN_PASSWORD_LENGTH, MAX_PASSWORD_LENGTH
+            ),
+        ));
+    }
+
+    let Some(token) = consume_token(db.as_ref(), &config, &reset.token, AuthTokenKind::PasswordReset).await? else {
+        return Ok(auth_error_page(
+            StatusCode::BAD_REQUEST,
+            "This reset link is invalid, expired or was already used.",
+        ));
+    };
+
+    let password_hash = hash_password(reset.new_password)
+        .await
+        .map_err(|err| AppError::internal(err.to_string()))?;
+
+    let update = async {
+        let txn = db.begin().await?;
+
+        password_credential::Entity::delete_by_id(token.user_id).exec(&txn).await?;
+        password_credential::ActiveModel {
+            user_id: Set(token.user_id),
+            password_hash: Set(password_hash),
+            failed_attempts: Set(0),
+            ..Default::default()
+        }
+        .insert(&txn)
+        .await?;
+
+        user::Entity::update_many()
+            .col_expr(user::Column::EmailVerifiedAt, Expr::value(Utc::now()))
+            .filter(user::Column::Uuid.eq(token.user_id))
+            .filter(user::Column::EmailVerifiedAt.is_null())
+            .exec(&txn)
+            .await?;
+
+        revoke_user_refresh_tokens(&txn, token.user_id).await?;
+
+        txn.commit().await
+    };
+
+    update.await?;
+    sessions.delete_for_user(token.user_id).await?;
+
+    Ok(Html(
+        r#"
+            <h1>Your password was changed.</h1>
+            <a href="/login">Back to login</a>
+        "#
+        .to_string(),
+    )
+    .into_response())
+}
+
+fn html_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
@@ -0,0 +1,337 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use axum::{http::StatusCode, response::Response};
+use oauth2::{basic::BasicClient, AuthUrl, TokenUrl};
+use openidconnect::{
+    core::{CoreAuthenticationFlow, CoreClient, CoreProviderMetadata},
+    reqwest::async_http_client,
+    url::Url,
+    AuthorizationCode, ClientId, ClientSecret, CsrfToken, IssuerUrl, Nonce, OAuth2TokenResponse,
+    PkceCodeChallenge, PkceCodeVerifier, RedirectUrl, Scope, TokenResponse,
+};
+use serde::Deserialize;
+
+use super::oauth_state::auth_error_page;
+use crate::config::{Config, NameClaim, ProviderConfig};
+use crate::redis_manager::auth_state::PendingAuth;
+
+const GOOGLE_ISSUER_URL: &str = "https://accounts.google.com";
+const GITLAB_ISSUER_URL: &str = "https://gitlab.com";
+const GITHUB_AUTH_URL: &str = "https://github.com/login/oauth/authorize";
+const GITHUB_TOKEN_URL: &str = "https://github.com/login/oauth/access_token";
+const GITHUB_API_URL: &str = "https://api.github.com";
+
+/// The identity asserted by a provider after a successful login.
+pub struct VerifiedIdentity {
+    pub subject: String,
+    pub email: String,
+    pub name: String,
+}
+
+enum Flow {
+    /// OpenID Connect, the identity is read from the verified ID token.
+    Oidc {
+        client: Box<CoreClient>,
+        name_claim: NameClaim,
+    },
+    /// Plain OAuth2, the identity is read from the GitHub REST API.
+    GitHub {
+        client: Box<BasicClient>,
+        http: reqwest::Client,
+    },
+}
+
+/// A configured identity provider, reachable under `/auth/:provider`.
+pub struct Provider {
+    pub name: String,
+    pub display_name: String,
+    scopes: Vec<String>,
+    flow: Flow,
+}
+
+/// All enabled identity providers keyed by their name.
+#[derive(Default)]
+pub struct ProviderRegistry {
+    providers: BTreeMap<String, Provider>,
+}
+
+impl ProviderRegistry {
+    /// Builds every configured provider.
+    ///
+    /// `google`, `gitlab` and `github` are built in, any other name is treated as a generic
+    /// OpenID Connect provider (e.g. Keycloak) with its own issuer URL.
+    pub async fn from_config(config: &Config) -> Arc<Self> {
+        let mut providers = BTreeMap::new();
+
+        for provider in &config.providers {
+            providers.insert(provider.name.clone(), Provider::new(provider, &config.public_base_url).await);
+        }
+
+        Arc::new(ProviderRegistry { providers })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Provider> {
+        self.providers.get(name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Provider> {
+        self.providers.values()
+    }
+}
+
+impl Provider {
+    async fn new(config: &ProviderConfig, public_base_url: &str) -> Provider {
+        let name = config.name.as_str();
+        let client_id = ClientId::new(config.client_id.clone());
+        let client_secret = ClientSecret::new(config.client_secret.clone());
+
+        let redirect_url = RedirectUrl::new(format!("{}/auth/{}/callback", public_base_url, name))
+            .expect("Invalid PUBLIC_BASE_URL");
+
+        let (display_name, default_scopes, flow) = match name {
+            "github" => {
+                let client = BasicClient::new(
+                    client_id,
+                    Some(client_secret),
+                    AuthUrl::new(GITHUB_AUTH_URL.to_string()).expect("Invalid AUTH_URL"),
+                    Some(TokenUrl::new(GITHUB_TOKEN_URL.to_string()).expect("Invalid TOKEN_URL")),
+                )
+                .set_redirect_uri(redirect_url);
+
+                let flow = Flow::GitHub {
+                    client: Box::new(client),
+                    http: reqwest::Client::new(),
+                };
+                ("GitHub", "read:user user:email", flow)
+            }
+            _ => {
+                let (display_name, default_issuer) = match name {
+                    "google" => ("Google", Some(GOOGLE_ISSUER_URL)),
+                    "gitlab" => ("GitLab", Some(GITLAB_ISSUER_URL)),
+                    _ => (name, None),
+                };
+
+                // the config makes sure providers without a default have an issuer
+                let issuer_url = config
+                    .issuer_url
+                    .clone()
+                    .or_else(|| default_issuer.map(str::to_string))
+                    .unwrap_or_default();
+                let issuer_url = IssuerUrl::new(issuer_url).expect("Invalid OIDC_ISSUER_URL");
+
+                let provider_metadata =
+                    CoreProviderMetadata::discover_async(issuer_url, async_http_client)
+                        .await
+                        .unwrap_or_else(|err| {
+                            panic!("Failed to discover the OpenID Connect provider {}: {:?}", name, err)
+                        });
+
+                let client = CoreClient::from_provider_metadata(
+                    provider_metadata,
+                    client_id,
+                    Some(client_secret),
+                )
+                .set_redirect_uri(redirect_url);
+
+                let flow = Flow::Oidc {
+                    client: Box::new(client),
+                    name_claim: config.name_claim,
+                };
+                (display_name, "email profile", flow)
+            }
+        };
+
+        let scopes = config
+            .scopes
+            .as_deref()
+            .unwrap_or(default_scopes)
+            .split_whitespace()
+            .map(str::to_string)
+            .collect();
+
+        Provider {
+            name: name.to_string(),
+            display_name: config.display_name.clone().unwrap_or_else(|| display_name.to_string()),
+            scopes,
+            flow,
+        }
+    }
+
+    /// Creates the provider URL for a new login together with the state, nonce and PKCE
+    /// verifier that have to be remembered until the callback.
+    pub fn authorize_url(&self) -> (Url, PendingAuth) {
+        let (pkce_challenge, pkce_verifier) = PkceCodeChallenge::new_random_sha256();
+        let scopes = self.scopes.iter().map(|scope| Scope::new(scope.clone()));
+
+        let (auth_url, csrf_token, nonce) = match &self.flow {
+            Flow::Oidc { client, .. } => client
+                .authorize_url(
+                    CoreAuthenticationFlow::AuthorizationCode,
+                    CsrfToken::new_random,
+                    Nonce::new_random,
+                )
+                .add_scopes(scopes)
+                .set_pkce_challenge(pkce_challenge)
+                .url(),
+            Flow::GitHub { client, .. } => {
+                let (auth_url, csrf_token) = client
+                    .authorize_url(CsrfToken::new_random)
+                    .add_scopes(scopes)
+                    .set_pkce_challenge(pkce_challenge)
+                    .url();
+                (auth_url, csrf_token, Nonce::new_random())
+            }
+        };
+
+        let pending = PendingAuth {
+            provider: self.name.clone(),
+            csrf_state: csrf_token.secret().clone(),
+            pkce_verifier: pkce_verifier.secret().clone(),
+            nonce: nonce.secret().clone(),
+            remember_me: false,
+        };
+
+        (auth_url, pending)
+    }
+
+    /// Exchanges the authorization code and resolves the identity behind it.
+    /// Only verified email addresses are accepted.
+    pub async fn exchange_and_verify(
+        &self,
+        code: &str,
+        pending: PendingAuth,
+    ) -> Result<VerifiedIdentity, Response> {
+        match &self.flow {
+            Flow::Oidc { client, name_claim } => {
+                verify_id_token(client, *name_claim, code, pending).await
+            }
+            Flow::GitHub { client, http } => {
+                let token = client
+                    .exchange_code(AuthorizationCode::new(code.to_string()))
+                    .set_pkce_verifier(PkceCodeVerifier::new(pending.pkce_verifier))
+                    .request_async(async_http_client)
+                    .await
+                    .map_err(|err| {
+                        tracing::error!("Failed to exchange code: {:?}", err);
+                        auth_error_page(StatusCode::BAD_GATEWAY, "Failed to exchange code")
+                    })?;
+
+                github_identity(http, token.access_token().secret()).await
+            }
+        }
+    }
+}
+
+/// Verifies the ID token's signature, issuer, audience, expiry and nonce.
+async fn verify_id_token(
+    client: &CoreClient,
+    name_claim: NameClaim,
+    code: &str,
+    pending: PendingAuth,
+) -> Result<VerifiedIdentity, Response> {
+    let token = client
+        .exchange_code(AuthorizationCode::new(code.to_string()))
+        .set_pkce_verifier(PkceCodeVerifier::new(pending.pkce_verifier))
+        .request_async(async_http_client)
+        .await
+        .map_err(|err| {
+            tracing::error!("Failed to exchange code: {:?}", err);
+            auth_error_page(StatusCode::BAD_GATEWAY, "Failed to exchange code")
+        })?;
+
+    let id_token = token.id_token().ok_or_else(|| {
+        auth_error_page(StatusCode::BAD_GATEWAY, "The provider did not return an ID token")
+    })?;
+
+    let claims = id_token
+        .claims(&client.id_token_verifier(), &Nonce::new(pending.nonce))
+        .map_err(|err| {
+            tracing::error!("Failed to verify ID token: {:?}", err);
+            auth_error_page(StatusCode::UNAUTHORIZED, "The ID token could not be verified")
+        })?;
+
+    let email = claims
+        .email()
+        .map(|email| email.to_string())
+        .ok_or_else(|| auth_error_page(StatusCode::UNAUTHORIZED, "The ID token carries no email"))?;
+
+    if claims.email_verified() != Some(true) {
+        return Err(auth_error_page(StatusCode::UNAUTHORIZED, "Email is not verified."));
+    }
+
+    let name = match name_claim {
+        NameClaim::Name => claims
+            .name()
+            .and_then(|name| name.get(None))
+            .map(|name| name.to_string()),
+        NameClaim::PreferredUsername => claims
+            .preferred_username()
+            .map(|username| username.to_string()),
+    }
+    .unwrap_or_else(|| email.clone());
+
+    Ok(VerifiedIdentity {
+        subject: claims.subject().to_string(),
+        email,
+        name,
+    })
+}
+
+#[derive(Deserialize)]
+struct GitHubUser {
+    id: u64,
+    login: String,
+    name: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct GitHubEmail {
+    email: String,
+    primary: bool,
+    verified: bool,
+}
+
+/// GitHub has no ID token, the user id and the primary verified email come from its REST API.
+async fn github_identity(http: &reqwest::Client, access_token: &str) -> Result<VerifiedIdentity, Response> {
+    let github_error = |err: reqwest::Error| {
+        tracing::error!("Failed to query the GitHub API: {:?}", err);
+        auth_error_page(StatusCode::BAD_GATEWAY, "Failed to query the GitHub API")
+    };
+
+    let get = |path: &str| {
+        http.get(format!("{GITHUB_API_URL}{path}"))
+            .bearer_auth(access_token)
+            .header(reqwest::header::USER_AGENT, "blog_proj")
+            .header(reqwest::header::ACCEPT, "application/vnd.github+json")
+    };
+
+    let user: GitHubUser = get("/user")
+        .send()
+        .await
+        .and_then(|res| res.error_for_status())
+        .map_err(github_error)?
+        .json()
+        .await
+        .map_err(github_error)?;
+
+    let emails: Vec<GitHubEmail> = get("/user/emails")
+        .send()
+        .await
+        .and_then(|res| res.error_for_status())
+        .map_err(github_error)?
+        .json()
+        .await
+        .map_err(github_error)?;
+
+    let email = emails
+        .into_iter()
+        .find(|email| email.primary && email.verified)
+        .map(|email| email.email)
+        .ok_or_else(|| auth_error_page(StatusCode::UNAUTHORIZED, "Email is not verified."))?;
+
+    Ok(VerifiedIdentity {
+        subject: user.id.to_string(),
+        email,
+        name: user.name.unwrap_or(user.login),
+    })
+}
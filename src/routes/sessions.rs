@@ -0,0 +1,158 @@
+use std::{cmp::Reverse, sync::Arc};
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    middleware::from_fn_with_state,
+    response::IntoResponse,
+    routing::{delete, get},
+    Json, Router,
+};
+use axum_extra::{headers, TypedHeader};
+use entity::user;
+use migration::sea_orm::{DatabaseConnection, EntityTrait};
+use serde_json::json;
+use uuid::Uuid;
+
+use super::extractors::AuthUser;
+use super::middlewares::require_csrf;
+use super::token_auth::revoke_user_refresh_tokens;
+use crate::error::{AppError, AppResult};
+use crate::models::user_models::SessionModel;
+use crate::policy::{authorize_user, Action};
+use crate::session_store::{SessionRecord, SessionStore};
+use crate::state::AppState;
+
+/// The sessions of the logged in user and the devices they were started from, so a lost
+/// laptop can be signed out from anywhere else.
+pub fn session_routes(state: &AppState) -> Router<AppState> {
+    Router::new()
+        .route("/me/sessions", get(list_sessions))
+        .route("/me/sessions/others", delete(revoke_other_sessions))
+        .route("/me/sessions/:id", delete(revoke_session))
+        .route("/user/:id/sessions", delete(revoke_user_sessions))
+        .route_layer(from_fn_with_state(state.clone(), require_csrf))
+}
+
+/// The session behind the cookie of this request, bearer requests have none.
+fn current_session_id(cookie: Option<TypedHeader<headers::Cookie>>) -> Option<Uuid> {
+    cookie?.get("session_id")?.parse().ok()
+}
+
+/// The live session of `user` behind the cookie of this request, if there is one.
+pub async fn current_session(
+    sessions: &dyn SessionStore,
+    cookie: Option<TypedHeader<headers::Cookie>>,
+    user: &user::Model,
+) -> AppResult<Option<SessionRecord>> {
+    let Some(session_id) = current_session_id(cookie) else {
+        return Ok(None);
+    };
+    Ok(sessions
+        .get(session_id)
+        .await?
+        .filter(|session| session.user_id == user.uuid))
+}
+
+/// Signs the user out on every device except the session `keep`: all other sessions are
+/// deleted and all refresh tokens revoked. Returns how many sessions were ended.
+pub async fn end_other_sessions(
+    db: &DatabaseConnection,
+    sessions: &dyn SessionStore,
+    user_id: Uuid,
+    keep: Option<Uuid>,
+) -> AppResult<usize> {
+    revoke_user_refresh_tokens(db, user_id).await?;
+
+    let mut ended = 0;
+    for session in sessions.list_for_user(user_id).await? {
+        if Some(session.session_id) != keep {
+            sessions.delete(session.session_id).await?;
+            ended += 1;
+        }
+    }
+    Ok(ended)
+}
+
+fn session_model(session: SessionRecord, current: Option<Uuid>) -> SessionModel {
+    SessionModel {
+        id: session.session_id,
+        current: current == Some(session.session_id),
+        user_agent: session.user_agent,
+        ip_address: session.ip_address,
+        created_at: session.created_at,
+        last_seen_at: session.last_seen_at,
+        expires_at: session.expires_at,
+        remember_me: session.remember_me,
+    }
+}
+
+async fn list_sessions(
+    AuthUser(user): AuthUser,
+    State(sessions): State<Arc<dyn SessionStore>>,
+    cookie: Option<TypedHeader<headers::Cookie>>,
+) -> AppResult<impl IntoResponse> {
+    let current = current_session_id(cookie);
+
+    let mut list = sessions.list_for_user(user.uuid).await?;
+    list.sort_by_key(|session| Reverse(session.last_seen_at));
+    let list: Vec<SessionModel> = list
+        .into_iter()
+        .map(|session| session_model(session, current))
+        .collect();
+    Ok((StatusCode::OK, Json(list)))
+}
+
+async fn revoke_session(
+    AuthUser(user): AuthUser,
+    State(sessions): State<Arc<dyn SessionStore>>,
+    Path(id): Path<Uuid>,
+) -> AppResult<impl IntoResponse> {
+    // sessions of other users look exactly like unknown ones
+    match sessions.get(id).await? {
+        Some(session) if session.user_id == user.uuid => {}
+        _ => return Err(AppError::NotFound("Session not found".to_string())),
+    }
+
+    sessions.delete(id).await?;
+    tracing::info!("User {} revoked the session {}", user.uuid, id);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Signs out every session of the user except the one making the request, and every token
+/// client. Bearer requests have no session to keep and are refused.
+async fn revoke_other_sessions(
+    AuthUser(user): AuthUser,
+    State(db): State<Arc<DatabaseConnection>>,
+    State(sessions): State<Arc<dyn SessionStore>>,
+    cookie: Option<TypedHeader<headers::Cookie>>,
+) -> AppResult<impl IntoResponse> {
+    let current = current_session(sessions.as_ref(), cookie, &user)
+        .await?
+        .ok_or_else(|| AppError::BadRequest("Only a session can sign out the others".to_string()))?;
+
+    let revoked = end_other_sessions(db.as_ref(), sessions.as_ref(), user.uuid, Some(current.session_id)).await?;
+
+    tracing::info!("User {} signed out {} other sessions", user.uuid, revoked);
+    Ok((StatusCode::OK, Json(json!({ "revoked": revoked }))))
+}
+
+/// Admins end every session of a user, e.g. after the account was compromised.
+async fn revoke_user_sessions(
+    AuthUser(actor): AuthUser,
+    State(db): State<Arc<DatabaseConnection>>,
+    State(sessions): State<Arc<dyn SessionStore>>,
+    Path(id): Path<Uuid>,
+) -> AppResult<impl IntoResponse> {
+    let target = user::Entity::find_by_id(id)
+        .one(db.as_ref())
+        .await?
+        .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+
+    authorize_user(&actor, Action::RevokeSessions, &target)?;
+
+    sessions.delete_for_user(target.uuid).await?;
+    revoke_user_refresh_tokens(db.as_ref(), target.uuid).await?;
+    tracing::info!("Admin {} revoked all sessions of user {}", actor.uuid, target.uuid);
+    Ok(StatusCode::NO_CONTENT)
+}
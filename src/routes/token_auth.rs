@@ -0,0 +1,194 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::post,
+    Json, Router,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{Duration, Utc};
+use entity::refresh_token;
+use migration::sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ColumnTrait, ConnectionTrait, DatabaseConnection, DbErr, EntityTrait,
+    QueryFilter, Set,
+};
+use rand::RngCore;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use super::mfa::{has_second_factor, verify_login_code, CodeCheck};
+use super::password::authenticate_password;
+use crate::config::Config;
+use crate::error::{AppError, AppResult};
+use crate::jwt::{self, ACCESS_TOKEN_TTL_MINUTES};
+use crate::models::user_models::{LoginModel, RefreshTokenModel, TokenLoginModel};
+use crate::state::AppState;
+
+const REFRESH_TOKEN_TTL_DAYS: i64 = 30;
+
+/// Token login for clients that can't hold the `SameSite=Strict` session cookie, e.g. the
+/// mobile app. Access tokens are JWTs accepted by `AuthUser`, refresh tokens are opaque,
+/// single-use and rotate on every refresh.
+pub fn token_auth_routes() -> Router<AppState> {
+    Router::new()
+        .route("/auth/token", post(token_login))
+        .route("/auth/token/refresh", post(refresh))
+        .route("/auth/token/revoke", post(revoke))
+}
+
+fn refresh_token_hash(token: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Stores a new refresh token in `family_id` and answers with it and a fresh access token.
+async fn issue_token_pair(
+    db: &DatabaseConnection,
+    config: &Config,
+    user_id: Uuid,
+    family_id: Uuid,
+) -> AppResult<Response> {
+    let access_token = jwt::issue_access_token(config, user_id)
+        .ok_or_else(|| AppError::internal("Failed to sign the access token"))?;
+
+    let mut secret = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut secret);
+    let refresh_token = URL_SAFE_NO_PAD.encode(secret);
+
+    refresh_token::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        family_id: Set(family_id),
+        user_id: Set(user_id),
+        token_hash: Set(refresh_token_hash(&refresh_token)),
+        expires_at: Set((Utc::now() + Duration::days(REFRESH_TOKEN_TTL_DAYS)).into()),
+        used_at: Set(None),
+        revoked_at: Set(None),
+        ..Default::default()
+    }
+    .insert(db)
+    .await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "access_token": access_token,
+            "token_type": "Bearer",
+            "expires_in": ACCESS_TOKEN_TTL_MINUTES * 60,
+            "refresh_token": refresh_token,
+        })),
+    )
+        .into_response())
+}
+
+async fn revoke_family(db: &DatabaseConnection, family_id: Uuid) -> Result<(), DbErr> {
+    refresh_token::Entity::update_many()
+        .col_expr(refresh_token::Column::RevokedAt, Expr::value(Utc::now()))
+        .filter(refresh_token::Column::FamilyId.eq(family_id))
+        .filter(refresh_token::Column::RevokedAt.is_null())
+        .exec(db)
+        .await?;
+    Ok(())
+}
+
+/// Signs every token client of the user out, together with deleting their sessions.
+pub async fn revoke_user_refresh_tokens<C: ConnectionTrait>(db: &C, user_id: Uuid) -> Result<(), DbErr> {
+    refresh_token::Entity::update_many()
+        .col_expr(refresh_token::Column::RevokedAt, Expr::value(Utc::now()))
+        .filter(refresh_token::Column::UserId.eq(user_id))
+        .filter(refresh_token::Column::RevokedAt.is_null())
+        .exec(db)
+        .await?;
+    Ok(())
+}
+
+/// Email and password login, users with a second factor also have to send `code`.
+async fn token_login(
+    State(db): State<Arc<DatabaseConnection>>,
+    State(config): State<Arc<Config>>,
+    Json(login): Json<TokenLoginModel>,
+) -> AppResult<Response> {
+    if !jwt::is_configured(&config) {
+        return Err(AppError::Unavailable("Token login is not configured".to_string()));
+    }
+
+    let login_data = LoginModel {
+        email: login.email,
+        password: login.password,
+        remember_me: false,
+    };
+    let user = authenticate_password(db.as_ref(), login_data).await?;
+
+    if has_second_factor(db.as_ref(), user.uuid).await? {
+        let code = login.code.ok_or(AppError::SecondFactorRequired)?;
+
+        match verify_login_code(db.as_ref(), &user, &code).await? {
+            CodeCheck::Valid => {}
+            CodeCheck::Invalid => return Err(AppError::Unauthorized("Invalid code".to_string())),
+            CodeCheck::Locked => {
+                return Err(AppError::TooManyRequests(
+                    "Too many invalid codes, try again later".to_string(),
+                ))
+            }
+        }
+    }
+
+    issue_token_pair(db.as_ref(), &config, user.uuid, Uuid::new_v4()).await
+}
+
+/// Trades a refresh token for a new pair. Every refresh token works once; presenting one
+/// that was already used means it leaked, so its whole family is revoked.
+async fn refresh(
+    State(db): State<Arc<DatabaseConnection>>,
+    State(config): State<Arc<Config>>,
+    Json(refresh): Json<RefreshTokenModel>,
+) -> AppResult<Response> {
+    let invalid = || AppError::Unauthorized("Refresh token is invalid or expired".to_string());
+
+    let current = refresh_token::Entity::find()
+        .filter(refresh_token::Column::TokenHash.eq(refresh_token_hash(&refresh.refresh_token)))
+        .one(db.as_ref())
+        .await?
+        .ok_or_else(invalid)?;
+
+    if current.revoked_at.is_some() || current.expires_at < Utc::now() {
+        return Err(invalid());
+    }
+
+    // claiming the token is a single conditional update, two concurrent refreshes can't both win
+    let claimed = refresh_token::Entity::update_many()
+        .col_expr(refresh_token::Column::UsedAt, Expr::value(Utc::now()))
+        .filter(refresh_token::Column::Id.eq(current.id))
+        .filter(refresh_token::Column::UsedAt.is_null())
+        .filter(refresh_token::Column::RevokedAt.is_null())
+        .exec(db.as_ref())
+        .await?;
+
+    if claimed.rows_affected == 0 {
+        tracing::warn!(
+            "Refresh token reuse for user {}, revoking token family {}",
+            current.user_id, current.family_id
+        );
+        revoke_family(db.as_ref(), current.family_id).await?;
+        return Err(invalid());
+    }
+
+    issue_token_pair(db.as_ref(), &config, current.user_id, current.family_id).await
+}
+
+/// Logout for token clients, ends the family of the given refresh token.
+async fn revoke(
+    State(db): State<Arc<DatabaseConnection>>,
+    Json(revoke): Json<RefreshTokenModel>,
+) -> AppResult<impl IntoResponse> {
+    let found = refresh_token::Entity::find()
+        .filter(refresh_token::Column::TokenHash.eq(refresh_token_hash(&revoke.refresh_token)))
+        .one(db.as_ref())
+        .await?;
+
+    if let Some(token) = found {
+        revoke_family(db.as_ref(), token.family_id).await?;
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
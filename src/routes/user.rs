@@ -1,109 +1,229 @@
 use crate::models;
-use crate::models::user_models::{CreateUserModel, GetUserModel, UpdateUserModel};
-use axum::extract::Path;
-use axum::routing::{get, post, put};
-use axum::{http::StatusCode, response::IntoResponse, Json, Router};
+use crate::models::user_models::{GetUserModel, PatchUserModel, UpdateUserModel};
+use axum::extract::{Path, Query, State};
+use axum::middleware::from_fn_with_state;
+use axum::routing::{get, patch, post, put};
+use axum::{
+    http::{header, HeaderMap, StatusCode},
+    response::IntoResponse,
+    Json, Router,
+};
 use axum::Extension;
+use axum_extra::{headers::IfMatch, TypedHeader};
+use chrono::Utc;
 use entity::user;
 use migration::sea_orm::ColumnTrait;
-use migration::sea_orm::{DatabaseConnection, EntityTrait, QueryFilter, Set};
-use models::user_models::{GetAllUsersModel, UserModelPub};
+use migration::sea_orm::{
+    sea_query::Expr, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder, QuerySelect, Set,
+};
+use models::user_models::{GetAllUsersModel, UserListQuery, UserModelPub};
 use std::sync::Arc;
 use uuid::Uuid;
 
-pub fn user_routes(db: Arc<DatabaseConnection>) -> Router {
+use super::etag::{check_if_match, etag, lost_update};
+use super::extractors::{AuthUser, ValidatedJson};
+use super::pagination::{next_link, page_size, Cursor};
+use super::middlewares::{require_auth, require_csrf};
+use crate::api_tokens::ApiScope;
+use crate::config::Config;
+use crate::error::{AppError, AppResult};
+use super::password::register;
+use crate::policy::{authorize_user, Action};
+use crate::state::AppState;
+
+pub fn user_routes(state: &AppState) -> Router<AppState> {
     Router::new()
+        .route("/user/update/:id", put(update_user))
+        .route("/users/:id", patch(patch_user))
+        .route_layer(from_fn_with_state(state.clone(), require_csrf))
+        .route_layer(from_fn_with_state(state.clone(), require_auth))
         .route("/users", get(get_all_users))
         .route("/privacy", get(|| async { "Privacy Policy" }))
         .route("/tos", get(|| async { "TOS" }))
         .route("/user/:id", get(get_user))
-        .route("/user/update/:id", put(update_user))
-        .route("/user/insert", post(register_user))
-        .layer(Extension(db))
+        // the old sign up path, it registers like /auth/password/register
+        .route("/user/insert", post(register))
+        .layer(Extension(ApiScope::UserWrite))
 }
 
-async fn get_all_users(Extension(db): Extension<Arc<DatabaseConnection>>) -> impl IntoResponse {
-    let users = entity::user::Entity::find().all(db.as_ref()).await;
-
-    match users {
-        Ok(res) => (
-            StatusCode::OK,
-            Json(GetAllUsersModel {
-                users: res
-                    .iter()
-                    .map(|u| UserModelPub {
-                        name: (*u.name).to_string(),
-                        email: (*u.email).to_string(),
-                    })
-                    .collect(),
-            }),
-        ),
-        Err(_) => (StatusCode::NOT_FOUND, Json::default()),
+/// One page of users, newest first unless `order=asc`.
+async fn get_all_users(
+    State(db): State<Arc<DatabaseConnection>>,
+    State(config): State<Arc<Config>>,
+    Query(query): Query<UserListQuery>,
+) -> AppResult<impl IntoResponse> {
+    let limit = page_size(query.limit);
+    let order = query.order.unwrap_or_default();
+
+    let mut select = user::Entity::find()
+        .order_by(user::Column::CreatedAt, order.order())
+        .order_by(user::Column::Uuid, order.order())
+        .limit(limit + 1);
+
+    if let Some(role) = query.role {
+        select = select.filter(user::Column::Role.eq(role));
+    }
+    if let Some(cursor) = &query.cursor {
+        let cursor = Cursor::<Uuid>::decode(cursor)?;
+        select = select.filter(cursor.after(user::Column::CreatedAt, user::Column::Uuid, order));
+    }
+
+    // one row more than asked tells whether there is a next page
+    let mut users = select.all(db.as_ref()).await?;
+    let next_cursor = if users.len() as u64 > limit {
+        users.truncate(limit as usize);
+        users.last().map(|last| {
+            Cursor {
+                created_at: last.created_at,
+                id: last.uuid,
+            }
+            .encode()
+        })
+    } else {
+        None
+    };
+
+    let mut headers = HeaderMap::new();
+    if let Some(cursor) = &next_cursor {
+        let next = UserListQuery {
+            cursor: Some(cursor.clone()),
+            ..query
+        };
+        if let Some(link) = next_link(&config.public_base_url, "/users", &next) {
+            headers.insert(header::LINK, link);
+        }
     }
+
+    Ok((
+        StatusCode::OK,
+        headers,
+        Json(GetAllUsersModel {
+            users: users
+                .into_iter()
+                .map(|u| UserModelPub {
+                    name: u.name,
+                    email: u.email,
+                })
+                .collect(),
+            next_cursor,
+        }),
+    ))
+}
+
+async fn find_user(db: &DatabaseConnection, id: Uuid) -> AppResult<user::Model> {
+    entity::user::Entity::find()
+        .filter(entity::user::Column::Uuid.eq(id))
+        .one(db)
+        .await?
+        .ok_or_else(|| AppError::NotFound("User not found".to_string()))
 }
 
 async fn get_user(
-    Extension(db): Extension<Arc<DatabaseConnection>>,
+    State(db): State<Arc<DatabaseConnection>>,
     Path(id): Path<Uuid>,
-) -> impl IntoResponse {
-    let user = entity::user::Entity::find()
-        .filter(entity::user::Column::Uuid.eq(id))
-        .one(db.as_ref())
-        .await
-        .unwrap()
-        .unwrap();
-    (
+) -> AppResult<impl IntoResponse> {
+    let user = find_user(db.as_ref(), id).await?;
+
+    Ok((
         StatusCode::OK,
+        TypedHeader(etag(user.version)),
         Json(GetUserModel {
             name: user.name.to_string(),
             email: user.email.to_string(),
             uuid: user.uuid,
         }),
-    )
+    ))
 }
 
 async fn update_user(
-    Extension(db): Extension<Arc<DatabaseConnection>>,
+    AuthUser(actor): AuthUser,
+    State(db): State<Arc<DatabaseConnection>>,
     Path(id): Path<Uuid>,
-    updated_user: Json<UpdateUserModel>,
-) -> impl IntoResponse {
-    let mut user: entity::user::ActiveModel = entity::user::Entity::find()
-        .filter(entity::user::Column::Uuid.eq(id))
-        .one(db.as_ref())
-        .await
-        .unwrap()
-        .unwrap()
-        .into();
+    if_match: Option<TypedHeader<IfMatch>>,
+    ValidatedJson(updated_user): ValidatedJson<UpdateUserModel>,
+) -> AppResult<impl IntoResponse> {
+    let target = find_user(db.as_ref(), id).await?;
 
-    user.name = Set(updated_user.name.clone());
+    authorize_user(&actor, Action::UpdateUser, &target)?;
+    check_if_match(if_match.as_deref(), target.version)?;
 
-    user::Entity::update(user).exec(db.as_ref()).await.unwrap();
+    let mut changes = user::ActiveModel {
+        name: Set(updated_user.name),
+        ..Default::default()
+    };
+
+    //only admins are allowed to promote or demote users
+    if let Some(role) = updated_user.role {
+        authorize_user(&actor, Action::ChangeRole, &target)?;
+        changes.role = Set(role);
+    }
 
-    (StatusCode::ACCEPTED, "Updated")
+    let user = save_user(db.as_ref(), &target, changes).await?;
+
+    Ok((
+        StatusCode::OK,
+        TypedHeader(etag(user.version)),
+        Json(GetUserModel {
+            name: user.name,
+            email: user.email,
+            uuid: user.uuid,
+        }),
+    ))
 }
 
-async fn register_user(
-    Extension(db): Extension<Arc<DatabaseConnection>>,
-    user_data : Json<CreateUserModel>,
-) -> impl IntoResponse {
+/// Changes only the fields present in the body. Send the `ETag` of the user as `If-Match`
+/// to get a 412 instead of overwriting somebody else's edit.
+async fn patch_user(
+    AuthUser(actor): AuthUser,
+    State(db): State<Arc<DatabaseConnection>>,
+    Path(id): Path<Uuid>,
+    if_match: Option<TypedHeader<IfMatch>>,
+    ValidatedJson(patch): ValidatedJson<PatchUserModel>,
+) -> AppResult<impl IntoResponse> {
+    let target = find_user(db.as_ref(), id).await?;
 
-    let user_id = Uuid::new_v4();
+    authorize_user(&actor, Action::UpdateUser, &target)?;
+    check_if_match(if_match.as_deref(), target.version)?;
 
-    let user_model = entity::user::ActiveModel{
-        name: Set(user_data.name.to_owned()),
-        email: Set(user_data.email.to_owned()),
-        uuid: Set(user_id),
-    };
-        
-    let new_user = entity::user::Entity::insert(user_model)
-        .exec(db.as_ref())
-        .await;
-
-    match new_user {
-        Ok(user) => (StatusCode::CREATED, format!("User was created successfully - > user : {:?}", user)), 
-        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, format!("The error occured! :("))
+    let mut changes = user::ActiveModel::default();
+    if let Some(name) = patch.name {
+        changes.name = Set(name);
+    }
+    if let Some(role) = patch.role {
+        authorize_user(&actor, Action::ChangeRole, &target)?;
+        changes.role = Set(role);
     }
+    let user = save_user(db.as_ref(), &target, changes).await?;
 
+    Ok((
+        StatusCode::OK,
+        TypedHeader(etag(user.version)),
+        Json(GetUserModel {
+            name: user.name,
+            email: user.email,
+            uuid: user.uuid,
+        }),
+    ))
 }
 
-//TODO: Upload Image
+/// Writes `changes` and bumps the version, unless somebody saved the user since `current`
+/// was read.
+async fn save_user(
+    db: &DatabaseConnection,
+    current: &user::Model,
+    changes: user::ActiveModel,
+) -> AppResult<user::Model> {
+    let result = user::Entity::update_many()
+        .set(changes)
+        .col_expr(user::Column::Version, Expr::col(user::Column::Version).add(1))
+        .col_expr(user::Column::UpdatedAt, Expr::value(Utc::now()))
+        .filter(user::Column::Uuid.eq(current.uuid))
+        .filter(user::Column::Version.eq(current.version))
+        .exec(db)
+        .await?;
+
+    if result.rows_affected == 0 {
+        return Err(lost_update());
+    }
+    find_user(db, current.uuid).await
+}
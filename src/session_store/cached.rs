@@ -0,0 +1,213 @@
+use std::sync::{
+    atomic::{AtomicI64, Ordering},
+    Arc,
+};
+
+use async_trait::async_trait;
+use chrono::Utc;
+use uuid::Uuid;
+
+use super::redis::CACHE_TTL_SECONDS;
+use super::{SessionError, SessionRecord, SessionStore};
+
+/// Cache-aside composite: `primary` (Postgres) is the source of truth, `cache` (Redis)
+/// answers most reads. Writes go to the primary first and are then mirrored into the cache;
+/// cache failures are logged and never fail the request.
+///
+/// A delete that can't reach the cache leaves a copy behind, so until every copy could have
+/// expired, reads skip the cache. Misses are filled from the primary without replacing what
+/// the cache holds, including the tombstone of a session deleted meanwhile.
+pub struct CachedSessionStore {
+    primary: Arc<dyn SessionStore>,
+    cache: Arc<dyn SessionStore>,
+    /// Unix time until which reads go to the primary only.
+    bypass_cache_until: AtomicI64,
+}
+
+impl CachedSessionStore {
+    pub fn new(primary: Arc<dyn SessionStore>, cache: Arc<dyn SessionStore>) -> Self {
+        CachedSessionStore {
+            primary,
+            cache,
+            bypass_cache_until: AtomicI64::new(0),
+        }
+    }
+
+    fn bypass_cache(&self) -> bool {
+        Utc::now().timestamp() < self.bypass_cache_until.load(Ordering::Relaxed)
+    }
+
+    async fn delete_cached(&self, session_id: Uuid) {
+        if let Err(err) = self.cache.delete(session_id).await {
+            log_cache_error("delete", err);
+            self.bypass_cache_until
+                .fetch_max(Utc::now().timestamp() + CACHE_TTL_SECONDS, Ordering::Relaxed);
+        }
+    }
+}
+
+fn log_cache_error(operation: &str, err: SessionError) {
+    tracing::warn!("Session cache {} failed: {}", operation, err);
+}
+
+#[async_trait]
+impl SessionStore for CachedSessionStore {
+    async fn create(&self, session: &SessionRecord) -> Result<(), SessionError> {
+        self.primary.create(session).await?;
+        if let Err(err) = self.cache.create(session).await {
+            log_cache_error("write", err);
+        }
+        Ok(())
+    }
+
+    async fn get(&self, session_id: Uuid) -> Result<Option<SessionRecord>, SessionError> {
+        if self.bypass_cache() {
+            return self.primary.get(session_id).await;
+        }
+
+        match self.cache.get(session_id).await {
+            Ok(Some(session)) => return Ok(Some(session)),
+            Ok(None) => {}
+            Err(err) => log_cache_error("read", err),
+        }
+
+        let session = self.primary.get(session_id).await?;
+        if let Some(session) = &session {
+            if let Err(err) = self.cache.create(session).await {
+                log_cache_error("write", err);
+            }
+        }
+        Ok(session)
+    }
+
+    async fn update(&self, session: &SessionRecord) -> Result<bool, SessionError> {
+        // the session was ended meanwhile, writing the cache would resurrect it
+        if !self.primary.update(session).await? {
+            self.delete_cached(session.session_id).await;
+            return Ok(false);
+        }
+
+        if let Err(err) = self.cache.update(session).await {
+            log_cache_error("write", err);
+            // a stale copy must not outlive the change
+            self.delete_cached(session.session_id).await;
+        }
+        Ok(true)
+    }
+
+    async fn delete(&self, session_id: Uuid) -> Result<(), SessionError> {
+        self.primary.delete(session_id).await?;
+        self.delete_cached(session_id).await;
+        Ok(())
+    }
+
+    async fn list_for_user(&self, user_id: Uuid) -> Result<Vec<SessionRecord>, SessionError> {
+        self.primary.list_for_user(user_id).await
+    }
+
+    async fn delete_for_user(&self, user_id: Uuid) -> Result<(), SessionError> {
+        let sessions = self.primary.list_for_user(user_id).await?;
+        self.primary.delete_for_user(user_id).await?;
+
+        for session in sessions {
+            self.delete_cached(session.session_id).await;
+        }
+        Ok(())
+    }
+
+    // cached copies expire on their own
+    async fn delete_expired(&self) -> Result<u64, SessionError> {
+        self.primary.delete_expired().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::redis::{ErrorKind, RedisError};
+
+    use super::*;
+    use crate::session_store::{ClientInfo, InMemorySessionStore, SessionLifetime};
+
+    /// A cache that became unreachable after it got its copies.
+    #[derive(Default)]
+    struct FailingDeletes(InMemorySessionStore);
+
+    fn unreachable() -> SessionError {
+        SessionError::Redis(RedisError::from((ErrorKind::IoError, "connection refused")))
+    }
+
+    #[async_trait]
+    impl SessionStore for FailingDeletes {
+        async fn create(&self, session: &SessionRecord) -> Result<(), SessionError> {
+            self.0.create(session).await
+        }
+
+        async fn get(&self, session_id: Uuid) -> Result<Option<SessionRecord>, SessionError> {
+            self.0.get(session_id).await
+        }
+
+        async fn update(&self, session: &SessionRecord) -> Result<bool, SessionError> {
+            self.0.update(session).await
+        }
+
+        async fn delete(&self, _session_id: Uuid) -> Result<(), SessionError> {
+            Err(unreachable())
+        }
+
+        async fn list_for_user(&self, user_id: Uuid) -> Result<Vec<SessionRecord>, SessionError> {
+            self.0.list_for_user(user_id).await
+        }
+
+        async fn delete_for_user(&self, _user_id: Uuid) -> Result<(), SessionError> {
+            Err(unreachable())
+        }
+
+        async fn delete_expired(&self) -> Result<u64, SessionError> {
+            self.0.delete_expired().await
+        }
+    }
+
+    fn session() -> SessionRecord {
+        SessionLifetime::default().new_session(Uuid::new_v4(), false, false, ClientInfo::default())
+    }
+
+    #[tokio::test]
+    async fn fills_the_cache_on_a_miss() {
+        let primary = Arc::new(InMemorySessionStore::default());
+        let cache = Arc::new(InMemorySessionStore::default());
+        let store = CachedSessionStore::new(primary.clone(), cache.clone());
+
+        let session = session();
+        primary.create(&session).await.unwrap();
+
+        assert_eq!(store.get(session.session_id).await.unwrap(), Some(session.clone()));
+        assert_eq!(cache.get(session.session_id).await.unwrap(), Some(session));
+    }
+
+    #[tokio::test]
+    async fn a_copy_left_by_a_failed_delete_is_not_served() {
+        let cache = Arc::new(FailingDeletes::default());
+        let store = CachedSessionStore::new(Arc::new(InMemorySessionStore::default()), cache.clone());
+
+        let session = session();
+        store.create(&session).await.unwrap();
+        store.delete(session.session_id).await.unwrap();
+
+        assert!(cache.get(session.session_id).await.unwrap().is_some());
+        assert_eq!(store.get(session.session_id).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn copies_left_by_a_failed_revoke_of_all_sessions_are_not_served() {
+        let store = CachedSessionStore::new(
+            Arc::new(InMemorySessionStore::default()),
+            Arc::new(FailingDeletes::default()),
+        );
+
+        let session = session();
+        store.create(&session).await.unwrap();
+        store.delete_for_user(session.user_id).await.unwrap();
+
+        assert_eq!(store.get(session.session_id).await.unwrap(), None);
+    }
+}
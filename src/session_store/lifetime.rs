@@ -0,0 +1,152 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Duration, Utc};
+use rand::RngCore;
+use uuid::Uuid;
+
+use super::{ClientInfo, SessionRecord};
+
+/// How long sessions live.
+///
+/// Regular sessions expire after `idle` without activity and are renewed while in use, but
+/// never beyond `absolute` after the login. "Remember me" sessions last `remember_me` from the
+/// login. Renewals also record the last activity and are written at most every
+/// `renew_interval` per session, so busy pages don't hit the store on every request.
+#[derive(Clone, Copy, Debug)]
+pub struct SessionLifetime {
+    pub idle: Duration,
+    pub absolute: Duration,
+    pub remember_me: Duration,
+    pub renew_interval: Duration,
+}
+
+impl Default for SessionLifetime {
+    fn default() -> Self {
+        SessionLifetime {
+            idle: Duration::minutes(60),
+            absolute: Duration::hours(12),
+            remember_me: Duration::days(30),
+            renew_interval: Duration::minutes(5),
+        }
+    }
+}
+
+/// 32 random bytes, unrelated to anything that shows up in URLs such as the OAuth state.
+fn new_csrf_token() -> String {
+    let mut token = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut token);
+    URL_SAFE_NO_PAD.encode(token)
+}
+
+impl SessionLifetime {
+    /// A fresh session starting now on the given device, with its own CSRF token.
+    pub fn new_session(
+        &self,
+        user_id: Uuid,
+        mfa_pending: bool,
+        remember_me: bool,
+        client: ClientInfo,
+    ) -> SessionRecord {
+        let now = Utc::now();
+        let mut session = SessionRecord {
+            session_id: Uuid::new_v4(),
+            user_id,
+            expires_at: now,
+            csrf_token: new_csrf_token(),
+            mfa_pending,
+            created_at: now,
+            remember_me,
+            user_agent: client.user_agent,
+            ip_address: client.ip_address,
+            last_seen_at: now,
+        };
+        session.expires_at = self.expiry_at(&session, now);
+        session
+    }
+
+    /// When the session would expire if it was used at `now`.
+    fn expiry_at(&self, session: &SessionRecord, now: DateTime<Utc>) -> DateTime<Utc> {
+        let (window, limit) = if session.remember_me {
+            (self.remember_me, self.remember_me)
+        } else {
+            (self.idle, self.absolute)
+        };
+        (now + window).min(session.created_at + limit)
+    }
+
+    /// The session after it was just used, `None` while the last renewal is recent enough.
+    pub fn renewed(&self, session: &SessionRecord) -> Option<SessionRecord> {
+        let now = Utc::now();
+        if now - session.last_seen_at < self.renew_interval {
+            return None;
+        }
+
+        let mut renewed = session.clone();
+        renewed.expires_at = self.expiry_at(session, now);
+        renewed.last_seen_at = now;
+        Some(renewed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(lifetime: &SessionLifetime, remember_me: bool) -> SessionRecord {
+        lifetime.new_session(Uuid::new_v4(), false, remember_me, ClientInfo::default())
+    }
+
+    #[test]
+    fn new_sessions_expire_after_the_idle_timeout() {
+        let lifetime = SessionLifetime::default();
+        let session = session(&lifetime, false);
+
+        assert_eq!(session.expires_at, session.created_at + lifetime.idle);
+    }
+
+    #[test]
+    fn remember_me_sessions_last_from_the_login() {
+        let lifetime = SessionLifetime::default();
+        let session = session(&lifetime, true);
+
+        assert_eq!(session.expires_at, session.created_at + lifetime.remember_me);
+        let later = session.created_at + Duration::days(10);
+        assert_eq!(lifetime.expiry_at(&session, later), session.created_at + lifetime.remember_me);
+    }
+
+    #[test]
+    fn expiry_never_passes_the_absolute_timeout() {
+        let lifetime = SessionLifetime::default();
+        let session = session(&lifetime, false);
+
+        let early = session.created_at + Duration::hours(1);
+        assert_eq!(lifetime.expiry_at(&session, early), early + lifetime.idle);
+
+        let late = session.created_at + lifetime.absolute - Duration::minutes(10);
+        assert_eq!(lifetime.expiry_at(&session, late), session.created_at + lifetime.absolute);
+    }
+
+    #[test]
+    fn renewal_waits_for_the_renew_interval() {
+        let lifetime = SessionLifetime::default();
+        let mut session = session(&lifetime, false);
+
+        assert!(lifetime.renewed(&session).is_none());
+
+        session.last_seen_at = Utc::now() - lifetime.renew_interval - Duration::seconds(1);
+        let renewed = lifetime.renewed(&session).expect("the session is due for renewal");
+        assert!(renewed.last_seen_at > session.last_seen_at);
+        assert!(renewed.expires_at > session.expires_at);
+        assert_eq!(renewed.csrf_token, session.csrf_token);
+    }
+
+    #[test]
+    fn renewal_keeps_an_old_session_at_its_absolute_limit() {
+        let lifetime = SessionLifetime::default();
+        let mut session = session(&lifetime, false);
+        session.created_at = Utc::now() - lifetime.absolute + Duration::minutes(1);
+        session.last_seen_at = Utc::now() - Duration::minutes(30);
+
+        let renewed = lifetime.renewed(&session).expect("the session is due for renewal");
+        assert_eq!(renewed.expires_at, session.created_at + lifetime.absolute);
+    }
+}
@@ -0,0 +1,70 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use async_trait::async_trait;
+use uuid::Uuid;
+
+use super::{SessionError, SessionRecord, SessionStore};
+
+/// Keeps sessions in process memory, for tests and single instance setups.
+#[derive(Default)]
+pub struct InMemorySessionStore {
+    sessions: Mutex<HashMap<Uuid, SessionRecord>>,
+}
+
+#[async_trait]
+impl SessionStore for InMemorySessionStore {
+    async fn create(&self, session: &SessionRecord) -> Result<(), SessionError> {
+        let mut sessions = self.sessions.lock().expect("session lock poisoned");
+        sessions.insert(session.session_id, session.clone());
+        Ok(())
+    }
+
+    async fn get(&self, session_id: Uuid) -> Result<Option<SessionRecord>, SessionError> {
+        let mut sessions = self.sessions.lock().expect("session lock poisoned");
+        match sessions.get(&session_id) {
+            Some(session) if session.is_expired() => {
+                sessions.remove(&session_id);
+                Ok(None)
+            }
+            session => Ok(session.cloned()),
+        }
+    }
+
+    async fn update(&self, session: &SessionRecord) -> Result<bool, SessionError> {
+        let mut sessions = self.sessions.lock().expect("session lock poisoned");
+        match sessions.get_mut(&session.session_id) {
+            Some(stored) => {
+                *stored = session.clone();
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    async fn delete(&self, session_id: Uuid) -> Result<(), SessionError> {
+        self.sessions.lock().expect("session lock poisoned").remove(&session_id);
+        Ok(())
+    }
+
+    async fn list_for_user(&self, user_id: Uuid) -> Result<Vec<SessionRecord>, SessionError> {
+        let sessions = self.sessions.lock().expect("session lock poisoned");
+        Ok(sessions
+            .values()
+            .filter(|session| session.user_id == user_id && !session.is_expired())
+            .cloned()
+            .collect())
+    }
+
+    async fn delete_for_user(&self, user_id: Uuid) -> Result<(), SessionError> {
+        let mut sessions = self.sessions.lock().expect("session lock poisoned");
+        sessions.retain(|_, session| session.user_id != user_id);
+        Ok(())
+    }
+
+    async fn delete_expired(&self) -> Result<u64, SessionError> {
+        let mut sessions = self.sessions.lock().expect("session lock poisoned");
+        let before = sessions.len();
+        sessions.retain(|_, session| !session.is_expired());
+        Ok((before - sessions.len()) as u64)
+    }
+}
@@ -0,0 +1,150 @@
+use std::{fmt, sync::Arc};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use entity::session;
+use migration::sea_orm::{DatabaseConnection, DbErr};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::config::SessionBackend;
+use crate::redis_manager::pool::RedisPool;
+
+pub mod cached;
+pub mod lifetime;
+pub mod memory;
+pub mod postgres;
+pub mod redis;
+
+pub use cached::CachedSessionStore;
+pub use lifetime::SessionLifetime;
+pub use memory::InMemorySessionStore;
+pub use postgres::PostgresSessionStore;
+pub use redis::RedisSessionStore;
+
+/// A login session as seen by every store.
+///
+/// Fields added after the first release carry serde defaults, so sessions cached in Redis
+/// by an older build still load.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionRecord {
+    pub session_id: Uuid,
+    pub user_id: Uuid,
+    pub expires_at: DateTime<Utc>,
+    pub csrf_token: String,
+    pub mfa_pending: bool,
+    #[serde(default = "Utc::now")]
+    pub created_at: DateTime<Utc>,
+    #[serde(default)]
+    pub remember_me: bool,
+    #[serde(default)]
+    pub user_agent: Option<String>,
+    #[serde(default)]
+    pub ip_address: Option<String>,
+    #[serde(default = "Utc::now")]
+    pub last_seen_at: DateTime<Utc>,
+}
+
+impl SessionRecord {
+    pub fn is_expired(&self) -> bool {
+        self.expires_at <= Utc::now()
+    }
+}
+
+impl From<session::Model> for SessionRecord {
+    fn from(session: session::Model) -> Self {
+        SessionRecord {
+            session_id: session.session_id,
+            user_id: session.user_id,
+            expires_at: session.expires_at.with_timezone(&Utc),
+            csrf_token: session.csfr_token,
+            mfa_pending: session.mfa_pending,
+            created_at: session.created_at.with_timezone(&Utc),
+            remember_me: session.remember_me,
+            user_agent: session.user_agent,
+            ip_address: session.ip_address,
+            last_seen_at: session.last_seen_at.with_timezone(&Utc),
+        }
+    }
+}
+
+/// The device a session is started from, as far as the request tells.
+#[derive(Clone, Debug, Default)]
+pub struct ClientInfo {
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum SessionError {
+    Database(DbErr),
+    Redis(::redis::RedisError),
+    Serialization(serde_json::Error),
+}
+
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SessionError::Database(err) => write!(f, "session database error: {}", err),
+            SessionError::Redis(err) => write!(f, "session redis error: {}", err),
+            SessionError::Serialization(err) => write!(f, "session serialization error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for SessionError {}
+
+impl From<DbErr> for SessionError {
+    fn from(err: DbErr) -> Self {
+        SessionError::Database(err)
+    }
+}
+
+impl From<::redis::RedisError> for SessionError {
+    fn from(err: ::redis::RedisError) -> Self {
+        SessionError::Redis(err)
+    }
+}
+
+impl From<serde_json::Error> for SessionError {
+    fn from(err: serde_json::Error) -> Self {
+        SessionError::Serialization(err)
+    }
+}
+
+/// Where login sessions live. Every handler reads and ends sessions through this trait,
+/// so logout and expiry take effect in every backing store at once.
+#[async_trait]
+pub trait SessionStore: Send + Sync {
+    async fn create(&self, session: &SessionRecord) -> Result<(), SessionError>;
+
+    /// Returns the session unless it is unknown or expired.
+    async fn get(&self, session_id: Uuid) -> Result<Option<SessionRecord>, SessionError>;
+
+    /// Replaces a stored session, e.g. once its second factor was completed. Returns
+    /// `false` and writes nothing when the session is gone, e.g. revoked in the meantime.
+    async fn update(&self, session: &SessionRecord) -> Result<bool, SessionError>;
+
+    async fn delete(&self, session_id: Uuid) -> Result<(), SessionError>;
+
+    /// All unexpired sessions of the user.
+    async fn list_for_user(&self, user_id: Uuid) -> Result<Vec<SessionRecord>, SessionError>;
+
+    async fn delete_for_user(&self, user_id: Uuid) -> Result<(), SessionError>;
+
+    /// Removes expired sessions nobody came back for, returns how many were removed.
+    async fn delete_expired(&self) -> Result<u64, SessionError>;
+}
+
+/// Builds the configured store, by default Postgres behind a Redis cache.
+pub fn session_store(backend: SessionBackend, db: Arc<DatabaseConnection>, redis: RedisPool) -> Arc<dyn SessionStore> {
+    match backend {
+        SessionBackend::Postgres => Arc::new(PostgresSessionStore::new(db)),
+        SessionBackend::Redis => Arc::new(RedisSessionStore::new(redis)),
+        SessionBackend::Memory => Arc::new(InMemorySessionStore::default()),
+        SessionBackend::Cached => Arc::new(CachedSessionStore::new(
+            Arc::new(PostgresSessionStore::new(db)),
+            Arc::new(RedisSessionStore::cache(redis)),
+        )),
+    }
+}
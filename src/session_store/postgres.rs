@@ -0,0 +1,101 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::Utc;
+use entity::session;
+use migration::sea_orm::{
+    sea_query::Expr, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, Set,
+};
+use uuid::Uuid;
+
+use super::{SessionError, SessionRecord, SessionStore};
+
+/// The `session` table, the durable source of truth.
+pub struct PostgresSessionStore {
+    db: Arc<DatabaseConnection>,
+}
+
+impl PostgresSessionStore {
+    pub fn new(db: Arc<DatabaseConnection>) -> Self {
+        PostgresSessionStore { db }
+    }
+}
+
+#[async_trait]
+impl SessionStore for PostgresSessionStore {
+    async fn create(&self, session: &SessionRecord) -> Result<(), SessionError> {
+        let new_session = session::ActiveModel {
+            session_id: Set(session.session_id),
+            user_id: Set(session.user_id),
+            expires_at: Set(session.expires_at.into()),
+            csfr_token: Set(session.csrf_token.clone()),
+            mfa_pending: Set(session.mfa_pending),
+            created_at: Set(session.created_at.into()),
+            remember_me: Set(session.remember_me),
+            user_agent: Set(session.user_agent.clone()),
+            ip_address: Set(session.ip_address.clone()),
+            last_seen_at: Set(session.last_seen_at.into()),
+            ..Default::default()
+        };
+
+        session::Entity::insert(new_session).exec(self.db.as_ref()).await?;
+        Ok(())
+    }
+
+    async fn get(&self, session_id: Uuid) -> Result<Option<SessionRecord>, SessionError> {
+        let session = session::Entity::find_by_id(session_id)
+            .one(self.db.as_ref())
+            .await?;
+
+        match session.map(SessionRecord::from) {
+            Some(session) if session.is_expired() => {
+                self.delete(session_id).await?;
+                Ok(None)
+            }
+            session => Ok(session),
+        }
+    }
+
+    async fn update(&self, session: &SessionRecord) -> Result<bool, SessionError> {
+        let result = session::Entity::update_many()
+            .col_expr(session::Column::ExpiresAt, Expr::value(session.expires_at))
+            .col_expr(session::Column::MfaPending, Expr::value(session.mfa_pending))
+            .col_expr(session::Column::LastSeenAt, Expr::value(session.last_seen_at))
+            .filter(session::Column::SessionId.eq(session.session_id))
+            .exec(self.db.as_ref())
+            .await?;
+        Ok(result.rows_affected > 0)
+    }
+
+    async fn delete(&self, session_id: Uuid) -> Result<(), SessionError> {
+        session::Entity::delete_by_id(session_id)
+            .exec(self.db.as_ref())
+            .await?;
+        Ok(())
+    }
+
+    async fn list_for_user(&self, user_id: Uuid) -> Result<Vec<SessionRecord>, SessionError> {
+        let sessions = session::Entity::find()
+            .filter(session::Column::UserId.eq(user_id))
+            .filter(session::Column::ExpiresAt.gt(Utc::now()))
+            .all(self.db.as_ref())
+            .await?;
+        Ok(sessions.into_iter().map(SessionRecord::from).collect())
+    }
+
+    async fn delete_for_user(&self, user_id: Uuid) -> Result<(), SessionError> {
+        session::Entity::delete_many()
+            .filter(session::Column::UserId.eq(user_id))
+            .exec(self.db.as_ref())
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_expired(&self) -> Result<u64, SessionError> {
+        let result = session::Entity::delete_many()
+            .filter(session::Column::ExpiresAt.lte(Utc::now()))
+            .exec(self.db.as_ref())
+            .await?;
+        Ok(result.rows_affected)
+    }
+}
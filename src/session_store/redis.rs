@@ -0,0 +1,209 @@
+use async_trait::async_trait;
+use chrono::Utc;
+use redis::{aio::ConnectionManager, AsyncCommands, ExistenceCheck, Script, SetExpiry, SetOptions};
+use uuid::Uuid;
+
+use super::{SessionError, SessionRecord, SessionStore};
+use crate::redis_manager::pool::RedisPool;
+
+/// Sessions as JSON under `session:{id}`, expiring together with the session. The set
+/// `user_sessions:{user_id}` indexes the sessions of every user.
+pub struct RedisSessionStore {
+    redis: RedisPool,
+    max_ttl_seconds: Option<i64>,
+}
+
+/// How long a cached copy may live. A copy that survived a failed delete, e.g. of a
+/// revoked session during a Redis outage, is served at most this long.
+pub const CACHE_TTL_SECONDS: i64 = 300;
+
+/// What a cache leaves under the key of a deleted session, so a read that fetched the
+/// session before the delete can't put its copy back.
+const TOMBSTONE: &str = "deleted";
+
+/// `SET key value EX ttl` unless the key holds the tombstone.
+const SET_UNLESS_DELETED: &str = r#"
+if redis.call('GET', KEYS[1]) == ARGV[3] then
+    return 0
+end
+redis.call('SET', KEYS[1], ARGV[1], 'EX', ARGV[2])
+return 1
+"#;
+
+fn session_key(session_id: Uuid) -> String {
+    format!("session:{session_id}")
+}
+
+fn user_sessions_key(user_id: Uuid) -> String {
+    format!("user_sessions:{user_id}")
+}
+
+impl RedisSessionStore {
+    pub fn new(redis: RedisPool) -> Self {
+        RedisSessionStore {
+            redis,
+            max_ttl_seconds: None,
+        }
+    }
+
+    /// A store used as the cache in front of Postgres. Its keys expire after a few minutes,
+    /// deleted sessions leave a tombstone for as long.
+    pub fn cache(redis: RedisPool) -> Self {
+        RedisSessionStore {
+            redis,
+            max_ttl_seconds: Some(CACHE_TTL_SECONDS),
+        }
+    }
+
+    fn is_cache(&self) -> bool {
+        self.max_ttl_seconds.is_some()
+    }
+
+    /// Seconds until the key of `session` expires, `None` once the session did.
+    fn ttl(&self, session: &SessionRecord) -> Option<u64> {
+        let ttl = (session.expires_at - Utc::now()).num_seconds();
+        let ttl = self.max_ttl_seconds.map_or(ttl, |max| ttl.min(max));
+        (ttl > 0).then_some(ttl as u64)
+    }
+
+    /// Deletes a session key, a cache leaves the tombstone for as long as a copy could live.
+    fn remove(&self, pipe: &mut redis::Pipeline, key: &str) {
+        if self.is_cache() {
+            pipe.set_ex(key, TOMBSTONE, CACHE_TTL_SECONDS as u64).ignore();
+        } else {
+            pipe.del(key).ignore();
+        }
+    }
+
+    async fn connection(&self) -> Result<ConnectionManager, SessionError> {
+        Ok(self.redis.connection().await?)
+    }
+}
+
+#[async_trait]
+impl SessionStore for RedisSessionStore {
+    async fn create(&self, session: &SessionRecord) -> Result<(), SessionError> {
+        let Some(ttl) = self.ttl(session) else {
+            return Ok(());
+        };
+
+        let mut con = self.connection().await?;
+        let value = serde_json::to_string(session)?;
+        if self.is_cache() {
+            // a cache is also filled from reads, which must not replace a tombstone or a
+            // newer copy
+            let options = SetOptions::default()
+                .conditional_set(ExistenceCheck::NX)
+                .with_expiration(SetExpiry::EX(ttl));
+            let written: Option<String> = con
+                .set_options(session_key(session.session_id), value, options)
+                .await?;
+            if written.is_none() {
+                return Ok(());
+            }
+        } else {
+            con.set_ex::<_, _, ()>(session_key(session.session_id), value, ttl).await?;
+        }
+        con.sadd::<_, _, ()>(user_sessions_key(session.user_id), session.session_id.to_string()).await?;
+        Ok(())
+    }
+
+    async fn get(&self, session_id: Uuid) -> Result<Option<SessionRecord>, SessionError> {
+        let mut con = self.connection().await?;
+        let value: Option<String> = con.get(session_key(session_id)).await?;
+
+        match value {
+            Some(value) if value == TOMBSTONE => Ok(None),
+            Some(value) => {
+                let session: SessionRecord = serde_json::from_str(&value)?;
+                Ok((!session.is_expired()).then_some(session))
+            }
+            None => Ok(None),
+        }
+    }
+
+    // a renewal racing a logout must not bring the deleted key back: SET XX, and in a cache,
+    // where the copy may have expired, a SET that stops at the tombstone
+    async fn update(&self, session: &SessionRecord) -> Result<bool, SessionError> {
+        let mut con = self.connection().await?;
+        let Some(ttl) = self.ttl(session) else {
+            con.del::<_, ()>(session_key(session.session_id)).await?;
+            return Ok(false);
+        };
+
+        let value = serde_json::to_string(session)?;
+        if self.is_cache() {
+            let written: i64 = Script::new(SET_UNLESS_DELETED)
+                .key(session_key(session.session_id))
+                .arg(value)
+                .arg(ttl)
+                .arg(TOMBSTONE)
+                .invoke_async(&mut con)
+                .await?;
+            return Ok(written == 1);
+        }
+
+        let options = SetOptions::default()
+            .conditional_set(ExistenceCheck::XX)
+            .with_expiration(SetExpiry::EX(ttl));
+        let written: Option<String> = con
+            .set_options(session_key(session.session_id), value, options)
+            .await?;
+        Ok(written.is_some())
+    }
+
+    async fn delete(&self, session_id: Uuid) -> Result<(), SessionError> {
+        let mut con = self.connection().await?;
+        let value: Option<String> = con.get(session_key(session_id)).await?;
+        let user_id = value
+            .filter(|value| value != TOMBSTONE)
+            .and_then(|value| serde_json::from_str::<SessionRecord>(&value).ok())
+            .map(|session| session.user_id);
+
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        self.remove(&mut pipe, &session_key(session_id));
+        if let Some(user_id) = user_id {
+            pipe.srem(user_sessions_key(user_id), session_id.to_string()).ignore();
+        }
+        pipe.query_async::<()>(&mut con).await?;
+        Ok(())
+    }
+
+    async fn list_for_user(&self, user_id: Uuid) -> Result<Vec<SessionRecord>, SessionError> {
+        let mut con = self.connection().await?;
+        let session_ids: Vec<String> = con.smembers(user_sessions_key(user_id)).await?;
+
+        let mut sessions = Vec::new();
+        for session_id in session_ids {
+            let Ok(parsed) = session_id.parse::<Uuid>() else {
+                continue;
+            };
+            match self.get(parsed).await? {
+                Some(session) => sessions.push(session),
+                // expired keys are gone already, drop them from the index too
+                None => con.srem::<_, _, ()>(user_sessions_key(user_id), session_id).await?,
+            }
+        }
+        Ok(sessions)
+    }
+
+    async fn delete_for_user(&self, user_id: Uuid) -> Result<(), SessionError> {
+        let mut con = self.connection().await?;
+        let session_ids: Vec<String> = con.smembers(user_sessions_key(user_id)).await?;
+
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        for session_id in session_ids {
+            self.remove(&mut pipe, &format!("session:{session_id}"));
+        }
+        pipe.del(user_sessions_key(user_id)).ignore();
+        pipe.query_async::<()>(&mut con).await?;
+        Ok(())
+    }
+
+    // session keys carry a TTL, stale index entries are dropped by `list_for_user`
+    async fn delete_expired(&self) -> Result<u64, SessionError> {
+        Ok(0)
+    }
+}
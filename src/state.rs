@@ -0,0 +1,130 @@
+use std::sync::Arc;
+
+use axum::extract::FromRef;
+use migration::sea_orm::DatabaseConnection;
+
+use crate::config::Config;
+use crate::mailer::{mailer_from_config, InMemoryMailer, Mailer};
+use crate::redis_manager::pool::RedisPool;
+use crate::routes::providers::ProviderRegistry;
+use crate::session_store::{session_store, InMemorySessionStore, SessionStore};
+use crate::storage::{storage_from_config, InMemoryStorage, Storage};
+
+/// Everything the handlers share, built once and cloned into every request.
+///
+/// Handlers take only the part they need, e.g. `State(db): State<Arc<DatabaseConnection>>`,
+/// through the `FromRef` impls below.
+#[derive(Clone)]
+pub struct AppState {
+    db: Arc<DatabaseConnection>,
+    redis: RedisPool,
+    sessions: Arc<dyn SessionStore>,
+    storage: Arc<dyn Storage>,
+    providers: Arc<ProviderRegistry>,
+    mailer: Arc<dyn Mailer>,
+    config: Arc<Config>,
+}
+
+impl AppState {
+    /// Connects every backend named in the config. Identity providers are discovered here,
+    /// so this fails loudly when one of them is unreachable.
+    pub async fn from_config(db: Arc<DatabaseConnection>, config: Config) -> Self {
+        let redis = RedisPool::new(&config.redis_url);
+        if let Err(err) = redis.connection().await {
+            tracing::warn!("Redis is not reachable, sessions are served from Postgres until it is: {}", err);
+        }
+
+        AppState {
+            sessions: session_store(config.session.store, db.clone(), redis.clone()),
+            storage: storage_from_config(&config.storage).await,
+            providers: ProviderRegistry::from_config(&config).await,
+            mailer: mailer_from_config(&config.mail),
+            config: Arc::new(config),
+            db,
+            redis,
+        }
+    }
+
+    /// Sessions, mails and uploads kept in memory and no identity providers, so tests can
+    /// build the whole app without Redis, SMTP or S3. Redis is only connected on first use.
+    pub fn in_memory(db: Arc<DatabaseConnection>, config: Config) -> Self {
+        AppState {
+            redis: RedisPool::new(&config.redis_url),
+            sessions: Arc::new(InMemorySessionStore::default()),
+            storage: Arc::new(InMemoryStorage::default()),
+            providers: Arc::new(ProviderRegistry::default()),
+            mailer: Arc::new(InMemoryMailer::default()),
+            config: Arc::new(config),
+            db,
+        }
+    }
+
+    pub fn with_sessions(mut self, sessions: Arc<dyn SessionStore>) -> Self {
+        self.sessions = sessions;
+        self
+    }
+
+    pub fn with_storage(mut self, storage: Arc<dyn Storage>) -> Self {
+        self.storage = storage;
+        self
+    }
+
+    pub fn with_mailer(mut self, mailer: Arc<dyn Mailer>) -> Self {
+        self.mailer = mailer;
+        self
+    }
+
+    pub fn db(&self) -> &Arc<DatabaseConnection> {
+        &self.db
+    }
+
+    pub fn sessions(&self) -> &Arc<dyn SessionStore> {
+        &self.sessions
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+}
+
+impl FromRef<AppState> for Arc<DatabaseConnection> {
+    fn from_ref(state: &AppState) -> Self {
+        state.db.clone()
+    }
+}
+
+impl FromRef<AppState> for RedisPool {
+    fn from_ref(state: &AppState) -> Self {
+        state.redis.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<dyn SessionStore> {
+    fn from_ref(state: &AppState) -> Self {
+        state.sessions.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<dyn Storage> {
+    fn from_ref(state: &AppState) -> Self {
+        state.storage.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<ProviderRegistry> {
+    fn from_ref(state: &AppState) -> Self {
+        state.providers.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<dyn Mailer> {
+    fn from_ref(state: &AppState) -> Self {
+        state.mailer.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<Config> {
+    fn from_ref(state: &AppState) -> Self {
+        state.config.clone()
+    }
+}
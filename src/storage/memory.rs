@@ -0,0 +1,34 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use async_trait::async_trait;
+use axum::body::Bytes;
+
+use super::{Storage, StorageError};
+
+/// Keeps files in memory so tests can upload without a bucket.
+#[derive(Default)]
+pub struct InMemoryStorage {
+    files: Mutex<HashMap<String, (String, Bytes)>>,
+}
+
+impl InMemoryStorage {
+    /// The content type and contents of a stored file.
+    pub fn get(&self, key: &str) -> Option<(String, Bytes)> {
+        self.files.lock().expect("storage lock poisoned").get(key).cloned()
+    }
+}
+
+#[async_trait]
+impl Storage for InMemoryStorage {
+    fn url(&self, key: &str) -> String {
+        format!("memory://{}", key)
+    }
+
+    async fn put(&self, key: &str, content_type: &str, bytes: Bytes) -> Result<(), StorageError> {
+        self.files
+            .lock()
+            .expect("storage lock poisoned")
+            .insert(key.to_string(), (content_type.to_string(), bytes));
+        Ok(())
+    }
+}
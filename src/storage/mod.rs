@@ -0,0 +1,45 @@
+use std::{fmt, sync::Arc};
+
+use async_trait::async_trait;
+use axum::body::Bytes;
+
+use crate::config::StorageConfig;
+use crate::validation::is_upload_key;
+
+pub mod memory;
+pub mod s3;
+
+pub use memory::InMemoryStorage;
+pub use s3::S3Storage;
+
+#[derive(Debug)]
+pub struct StorageError(pub String);
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "failed to store file: {}", self.0)
+    }
+}
+
+impl std::error::Error for StorageError {}
+
+/// Keeps uploaded files, e.g. images for blog posts.
+#[async_trait]
+pub trait Storage: Send + Sync {
+    /// The public URL a stored file is served from.
+    fn url(&self, key: &str) -> String;
+
+    /// Whether `reference` is the key or the public URL of an upload in this storage.
+    fn holds(&self, reference: &str) -> bool {
+        let key = reference.strip_prefix(self.url("").as_str()).unwrap_or(reference);
+        is_upload_key(key)
+    }
+
+    async fn put(&self, key: &str, content_type: &str, bytes: Bytes) -> Result<(), StorageError>;
+}
+
+/// Builds the S3 bucket from the config, credentials and region come from the standard
+/// `AWS_*` variables.
+pub async fn storage_from_config(config: &StorageConfig) -> Arc<dyn Storage> {
+    Arc::new(S3Storage::from_env(&config.s3_bucket).await)
+}
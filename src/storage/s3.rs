@@ -0,0 +1,46 @@
+use async_trait::async_trait;
+use aws_config::BehaviorVersion;
+use aws_sdk_s3::{primitives::ByteStream, Client};
+use axum::body::Bytes;
+
+use super::{Storage, StorageError};
+
+/// Stores files in an S3 bucket.
+pub struct S3Storage {
+    client: Client,
+    bucket: String,
+}
+
+impl S3Storage {
+    pub fn new(client: Client, bucket: impl Into<String>) -> Self {
+        S3Storage { client, bucket: bucket.into() }
+    }
+
+    pub async fn from_env(bucket: &str) -> Self {
+        let aws_configuration = aws_config::load_defaults(BehaviorVersion::latest()).await;
+        S3Storage::new(Client::new(&aws_configuration), bucket)
+    }
+}
+
+#[async_trait]
+impl Storage for S3Storage {
+    fn url(&self, key: &str) -> String {
+        format!("https://{}.s3.amazonaws.com/{}", self.bucket, key)
+    }
+
+    async fn put(&self, key: &str, content_type: &str, bytes: Bytes) -> Result<(), StorageError> {
+        let content_length = bytes.len() as i64;
+
+        self.client
+            .put_object()
+            .bucket(&self.bucket)
+            .content_type(content_type)
+            .content_length(content_length)
+            .key(key)
+            .body(ByteStream::from(bytes))
+            .send()
+            .await
+            .map_err(|err| StorageError(format!("{err}")))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,194 @@
+use uuid::Uuid;
+
+use crate::error::{AppError, AppResult, FieldError};
+use crate::storage::Storage;
+
+pub const MIN_PASSWORD_LENGTH: usize = 8;
+/// Argon2 hashes the whole password, so an unbounded one is a cheap way to burn CPU.
+pub const MAX_PASSWORD_LENGTH: usize = 1024;
+pub const MAX_NAME_LENGTH: usize = 100;
+pub const MAX_EMAIL_LENGTH: usize = 254;
+pub const MAX_TITLE_LENGTH: usize = 200;
+pub const MAX_CONTENT_LENGTH: usize = 100_000;
+pub const MAX_IMAGES: usize = 20;
+pub const MAX_TAGS: usize = 10;
+pub const MAX_TAG_LENGTH: usize = 32;
+
+/// A request body that lists its own rules, checked by the `ValidatedJson` extractor.
+pub trait Validate {
+    fn validate(&self, check: &mut Validator<'_>);
+}
+
+/// Collects every failing field of a body instead of stopping at the first one, so a
+/// form can mark all of them at once.
+#[derive(Default)]
+pub struct Validator<'a> {
+    storage: Option<&'a dyn Storage>,
+    errors: Vec<FieldError>,
+}
+
+impl<'a> Validator<'a> {
+    /// A validator that also accepts file URLs handed out by `storage`, without one only
+    /// bare upload keys pass `stored_files`.
+    pub fn with_storage(storage: &'a dyn Storage) -> Self {
+        Validator {
+            storage: Some(storage),
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn not_blank(&mut self, field: &str, value: &str) -> &mut Self {
+        if value.trim().is_empty() {
+            self.fail(field, "Must not be empty");
+        }
+        self
+    }
+
+    pub fn max_length(&mut self, field: &str, value: &str, max: usize) -> &mut Self {
+        if value.chars().count() > max {
+            self.fail(field, format!("Must have at most {} characters", max));
+        }
+        self
+    }
+
+    pub fn email(&mut self, field: &str, value: &str) -> &mut Self {
+        if value.len() > MAX_EMAIL_LENGTH || !is_email(value) {
+            self.fail(field, "Must be a valid email address");
+        }
+        self
+    }
+
+    pub fn password(&mut self, field: &str, value: &str) -> &mut Self {
+        let length = value.chars().count();
+        if length < MIN_PASSWORD_LENGTH {
+            self.fail(field, format!("Password must have at least {} characters", MIN_PASSWORD_LENGTH));
+        } else if length > MAX_PASSWORD_LENGTH {
+            self.fail(field, format!("Password must have at most {} characters", MAX_PASSWORD_LENGTH));
+        }
+        self
+    }
+
+    /// Only files uploaded through `/upload` may be attached, never arbitrary links to
+    /// other hosts.
+    pub fn stored_files(&mut self, field: &str, values: &[String]) -> &mut Self {
+        if values.len() > MAX_IMAGES {
+            self.fail(field, format!("At most {} files are allowed", MAX_IMAGES));
+        }
+        for (index, value) in values.iter().enumerate() {
+            let stored = match self.storage {
+                Some(storage) => storage.holds(value),
+                None => is_upload_key(value),
+            };
+            if !stored {
+                self.fail(&format!("{}[{}]", field, index), "Must be a file uploaded to this site");
+            }
+        }
+        self
+    }
+
+    /// Tags are matched exactly when filtering, so they are kept to lowercase words.
+    pub fn tags(&mut self, field: &str, values: &[String]) -> &mut Self {
+        if values.len() > MAX_TAGS {
+            self.fail(field, format!("At most {} tags are allowed", MAX_TAGS));
+        }
+        for (index, value) in values.iter().enumerate() {
+            let valid = !value.is_empty()
+                && value.chars().count() <= MAX_TAG_LENGTH
+                && value.chars().all(|c| c.is_lowercase() || c.is_ascii_digit() || c == '-');
+            if !valid {
+                self.fail(
+                    &format!("{}[{}]", field, index),
+                    format!("Must have 1 to {} lowercase letters, digits or '-'", MAX_TAG_LENGTH),
+                );
+            }
+        }
+        self
+    }
+
+    pub fn finish(self) -> AppResult<()> {
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(AppError::Validation(self.errors))
+        }
+    }
+
+    /// Records a rule that does not fit the helpers above.
+    pub fn fail(&mut self, field: &str, message: impl Into<String>) {
+        self.errors.push(FieldError::new(field, message));
+    }
+}
+
+/// Uploads are stored under a fresh hyphenated uuid.
+pub fn is_upload_key(value: &str) -> bool {
+    Uuid::parse_str(value).is_ok_and(|id| id.to_string() == value)
+}
+
+/// A syntax check only, whether the mailbox exists is left to the verification email.
+fn is_email(value: &str) -> bool {
+    let Some((local, domain)) = value.rsplit_once('@') else {
+        return false;
+    };
+    let labels_valid = domain.split('.').all(|label| {
+        !label.is_empty()
+            && !label.starts_with('-')
+            && !label.ends_with('-')
+            && label.chars().all(|c| c.is_alphanumeric() || c == '-')
+    });
+
+    !local.is_empty()
+        && local.len() <= 64
+        && !local.contains('@')
+        && !local.chars().any(|c| c.is_whitespace() || c.is_control())
+        && domain.contains('.')
+        && labels_valid
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_plain_addresses() {
+        for value in ["user@example.com", "first.last+tag@mail.example.co.uk", "jörg@bücher.de"] {
+            assert!(is_email(value), "{}", value);
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_addresses() {
+        let values = [
+            "",
+            "user",
+            "@example.com",
+            "user@",
+            "user@localhost",
+            "user@@example.com",
+            "user name@example.com",
+            "user@example..com",
+            "user@-example.com",
+            "user@example-.com",
+            "user@exa_mple.com",
+        ];
+        for value in values {
+            assert!(!is_email(value), "{}", value);
+        }
+        assert!(!is_email(&format!("{}@example.com", "a".repeat(65))));
+    }
+
+    #[test]
+    fn reports_every_failing_field() {
+        let mut check = Validator::default();
+        check
+            .not_blank("name", " ")
+            .email("email", "nope")
+            .password("password", "short")
+            .tags("tags", &["Rust".to_string(), "ok".to_string()]);
+
+        let Err(AppError::Validation(errors)) = check.finish() else {
+            panic!("expected a validation error");
+        };
+        let fields: Vec<_> = errors.iter().map(|error| error.field.as_str()).collect();
+        assert_eq!(fields, ["name", "email", "password", "tags[0]"]);
+    }
+}
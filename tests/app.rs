@@ -0,0 +1,113 @@
+//! The whole router on top of `AppState::in_memory`. None of these requests get as far as
+//! the database, which stays disconnected.
+
+use std::sync::Arc;
+
+use axum::{
+    body::{to_bytes, Body},
+    http::{header, Request, StatusCode},
+    Router,
+};
+use blog_proj::{
+    app,
+    config::Config,
+    session_store::{ClientInfo, SessionLifetime, SessionRecord},
+    state::AppState,
+};
+use migration::sea_orm::DatabaseConnection;
+use serde_json::Value;
+use tower::ServiceExt;
+use uuid::Uuid;
+
+fn state() -> AppState {
+    let config = Config::from_values(&[
+        ("DATABASE_URL", "postgres://localhost/blog_test"),
+        ("REDIS_URL", "redis://localhost"),
+        ("AWS_S3_BUCKET", "blog-test"),
+        ("TOKEN_SIGNING_KEY", "test-signing-key"),
+        ("GOOGLE_OAUTH_CLIENT_ID", "test-client"),
+        ("GOOGLE_OAUTH_CLIENT_SECRET", "test-secret"),
+    ])
+    .expect("valid configuration");
+
+    AppState::in_memory(Arc::new(DatabaseConnection::Disconnected), config)
+}
+
+async fn signed_in(state: &AppState) -> SessionRecord {
+    let session = SessionLifetime::default().new_session(Uuid::new_v4(), false, false, ClientInfo::default());
+    state.sessions().create(&session).await.unwrap();
+    session
+}
+
+fn cookie(session: &SessionRecord) -> String {
+    format!("session_id={}; csrf_token={}", session.session_id, session.csrf_token)
+}
+
+async fn send(app: Router, request: Request<Body>) -> (StatusCode, Value) {
+    let response = app.oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+#[tokio::test]
+async fn creating_a_blog_needs_a_login() {
+    let request = Request::post("/blog/insert")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(r#"{"title": "Hello", "content": "World"}"#))
+        .unwrap();
+
+    let (status, problem) = send(app(state()), request).await;
+
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(problem["status"], 401);
+    assert!(problem["request_id"].is_string());
+}
+
+#[tokio::test]
+async fn cookie_requests_without_the_csrf_token_are_refused() {
+    let state = state();
+    let session = signed_in(&state).await;
+
+    let request = Request::delete("/me/sessions/others")
+        .header(header::COOKIE, cookie(&session))
+        .body(Body::empty())
+        .unwrap();
+
+    let (status, _) = send(app(state.clone()), request).await;
+
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert!(state.sessions().get(session.session_id).await.unwrap().is_some());
+}
+
+#[tokio::test]
+async fn logout_is_a_post_with_the_csrf_token() {
+    let state = state();
+    let session = signed_in(&state).await;
+
+    let get = Request::get("/logout")
+        .header(header::COOKIE, cookie(&session))
+        .body(Body::empty())
+        .unwrap();
+    let (status, _) = send(app(state.clone()), get).await;
+    assert_eq!(status, StatusCode::METHOD_NOT_ALLOWED);
+
+    let forged = Request::post("/logout")
+        .header(header::COOKIE, cookie(&session))
+        .body(Body::empty())
+        .unwrap();
+    let (status, _) = send(app(state.clone()), forged).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert!(state.sessions().get(session.session_id).await.unwrap().is_some());
+
+    let logout = Request::post("/logout")
+        .header(header::COOKIE, cookie(&session))
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .body(Body::from(format!("csrf_token={}", session.csrf_token)))
+        .unwrap();
+    let response = app(state.clone()).oneshot(logout).await.unwrap();
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    let cleared: Vec<_> = response.headers().get_all(header::SET_COOKIE).iter().collect();
+    assert!(cleared.iter().any(|cookie| cookie.to_str().unwrap().starts_with("session_id=deleted; Path=/;")));
+    assert!(state.sessions().get(session.session_id).await.unwrap().is_none());
+}